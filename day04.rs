@@ -1,3 +1,7 @@
+mod grid;
+
+use grid::Grid;
+use std::collections::VecDeque;
 use std::env;
 use std::fs;
 use std::io::{self, Write};
@@ -6,116 +10,570 @@ use std::path::Path;
 const DEFAULT_INPUT: &str = "../input/day04.txt";
 const DEFAULT_OUTPUT: &str = "output/day04.txt";
 
-fn read_grid(filename: &str) -> Result<Vec<Vec<char>>, std::io::Error> {
-    let content = fs::read_to_string(filename)?;
-    let grid: Vec<Vec<char>> = content
-        .lines()
-        .map(|line| line.trim_end().chars().collect())
-        .filter(|row: &Vec<char>| !row.is_empty())
-        .collect();
-    Ok(grid)
+type Solver = fn(&str, &Rule) -> io::Result<usize>;
+
+#[derive(Debug, Clone)]
+enum Neighbourhood {
+    VonNeumann(usize),
+    Moore(usize),
+    Custom(Vec<(isize, isize)>),
+}
+
+impl Neighbourhood {
+    /// A named neighbourhood of the given radius (1 if unset), or custom
+    /// `dr,dc` offsets separated by `;`, which take no radius.
+    fn parse(name: &str, radius: Option<usize>) -> Result<Self, String> {
+        match name {
+            "von-neumann" => Ok(Neighbourhood::VonNeumann(radius.unwrap_or(1))),
+            "moore" => Ok(Neighbourhood::Moore(radius.unwrap_or(1))),
+            _ if !name.contains(',') => Err(format!("Unknown neighbourhood: {}", name)),
+            _ if radius.is_some() => {
+                Err("--radius cannot be combined with custom offsets".to_string())
+            }
+            _ => {
+                let mut offsets = Vec::new();
+                for pair in name.split(';').filter(|p| !p.trim().is_empty()) {
+                    let (dr, dc) = pair
+                        .split_once(',')
+                        .ok_or_else(|| format!("Invalid offset: {}", pair))?;
+                    let dr = dr
+                        .trim()
+                        .parse::<isize>()
+                        .map_err(|_| "Invalid row offset")?;
+                    let dc = dc
+                        .trim()
+                        .parse::<isize>()
+                        .map_err(|_| "Invalid column offset")?;
+                    offsets.push((dr, dc));
+                }
+                Ok(Neighbourhood::Custom(offsets))
+            }
+        }
+    }
+
+    fn offsets(&self) -> Vec<(isize, isize)> {
+        let square = |r: usize| {
+            let r = r as isize;
+            (-r..=r)
+                .flat_map(move |dr| (-r..=r).map(move |dc| (dr, dc)))
+                .filter(|&offset| offset != (0, 0))
+        };
+        match self {
+            Neighbourhood::VonNeumann(r) => square(*r)
+                .filter(|(dr, dc)| dr.unsigned_abs() + dc.unsigned_abs() <= *r)
+                .collect(),
+            Neighbourhood::Moore(r) => square(*r).collect(),
+            Neighbourhood::Custom(offsets) => offsets.clone(),
+        }
+    }
 }
 
-fn count_neighbors(grid: &[Vec<char>], r: usize, c: usize) -> usize {
-    let rows = grid.len() as isize;
-    let cols = grid[0].len() as isize;
-    let mut count = 0;
+/// Which cells count as rolls and when a roll is accessible to the forklifts.
+/// The default is the puzzle's rule: an `'@'` with fewer than 4 of its 8 neighbours occupied.
+#[derive(Debug, Clone)]
+struct Rule {
+    roll: char,
+    empty: char,
+    threshold: usize,
+    offsets: Vec<(isize, isize)>,
+    wrap: bool,
+}
+
+impl Default for Rule {
+    fn default() -> Self {
+        Rule {
+            roll: '@',
+            empty: '.',
+            threshold: 4,
+            offsets: Neighbourhood::Moore(1).offsets(),
+            wrap: false,
+        }
+    }
+}
+
+impl Rule {
+    /// Calls `f` with each neighbor of `(r, c)` reached by `offsets`. Takes a
+    /// callback rather than returning an iterator so the wrapping and bounded
+    /// walks don't need boxing on this hot path.
+    fn for_each_neighbor<F>(
+        &self,
+        grid: &Grid<char>,
+        r: usize,
+        c: usize,
+        offsets: &[(isize, isize)],
+        f: F,
+    ) where
+        F: FnMut((usize, usize)),
+    {
+        if self.wrap {
+            grid.neighbors_wrapping(r, c, offsets).for_each(f)
+        } else {
+            grid.neighbors_with(r, c, offsets).for_each(f)
+        }
+    }
 
-    for dr in -1..=1 {
-        for dc in -1..=1 {
-            if dr == 0 && dc == 0 {
-                continue;
+    fn count_neighbors(&self, grid: &Grid<char>, r: usize, c: usize) -> usize {
+        let mut count = 0;
+        self.for_each_neighbor(grid, r, c, &self.offsets, |pos| {
+            if grid[pos] == self.roll {
+                count += 1;
             }
+        });
+        count
+    }
+
+    fn is_accessible(&self, grid: &Grid<char>, r: usize, c: usize) -> bool {
+        grid[(r, c)] == self.roll && self.count_neighbors(grid, r, c) < self.threshold
+    }
+}
 
-            let nr = r as isize + dr;
-            let nc = c as isize + dc;
+struct Options {
+    input_file: String,
+    rule: Rule,
+    stats: bool,
+    frames_dir: Option<String>,
+    ppm: bool,
+    bitset: bool,
+    validate: bool,
+}
 
-            if nr >= 0 && nr < rows && nc >= 0 && nc < cols {
-                if grid[nr as usize][nc as usize] == '@' {
-                    count += 1;
-                }
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        input_file: DEFAULT_INPUT.to_string(),
+        rule: Rule::default(),
+        stats: false,
+        frames_dir: None,
+        ppm: false,
+        bitset: false,
+        validate: false,
+    };
+    let rule = &mut options.rule;
+    let mut neighbourhood = "moore".to_string();
+    let mut radius = None;
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        let mut value = |name: &str| {
+            iter.next()
+                .cloned()
+                .ok_or_else(|| format!("Missing value for {}", name))
+        };
+        let single_char = |s: String| {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => Ok(ch),
+                _ => Err(format!("Expected a single character, got: {}", s)),
+            }
+        };
+        match arg.as_str() {
+            "--threshold" => {
+                rule.threshold = value(arg)?.parse().map_err(|_| "Invalid threshold")?;
             }
+            "--neighbourhood" => neighbourhood = value(arg)?,
+            "--radius" => radius = Some(value(arg)?.parse().map_err(|_| "Invalid radius")?),
+            "--roll" => rule.roll = single_char(value(arg)?)?,
+            "--empty" => rule.empty = single_char(value(arg)?)?,
+            "--wrap" => rule.wrap = true,
+            "--stats" => options.stats = true,
+            "--frames" => options.frames_dir = Some(value(arg)?),
+            "--ppm" => options.ppm = true,
+            "--bitset" => options.bitset = true,
+            "--validate" => options.validate = true,
+            _ if arg.starts_with("--") => return Err(format!("Unknown option: {}", arg)),
+            _ => options.input_file = arg.clone(),
         }
     }
-    count
+
+    rule.offsets = Neighbourhood::parse(&neighbourhood, radius)?.offsets();
+    if (options.bitset || options.validate) && options.rule.wrap {
+        return Err("The bitset representation does not support --wrap".to_string());
+    }
+    Ok(options)
 }
 
-fn part_one(filename: &str) -> io::Result<usize> {
-    let grid = read_grid(filename)?;
+fn read_grid(filename: &str, empty: char) -> Result<Grid<char>, std::io::Error> {
+    let content = fs::read_to_string(filename)?;
+    let lines = content
+        .lines()
+        .map(|line| line.trim_end())
+        .filter(|line| !line.is_empty());
+    Ok(Grid::from_lines(lines, empty))
+}
+
+fn part_one(filename: &str, rule: &Rule) -> io::Result<usize> {
+    let grid = read_grid(filename, rule.empty)?;
     if grid.is_empty() {
         return Ok(0);
     }
 
-    let rows = grid.len();
-    let cols = grid[0].len();
-    let mut accessible_rolls = 0;
+    let accessible_rolls = grid
+        .positions()
+        .filter(|&(r, c)| rule.is_accessible(&grid, r, c))
+        .count();
 
-    for r in 0..rows {
-        for c in 0..cols {
-            if grid[r][c] == '@' {
-                if count_neighbors(&grid, r, c) < 4 {
-                    accessible_rolls += 1;
-                }
+    Ok(accessible_rolls)
+}
+
+/// Removes rolls until none are accessible, returning the cells removed in each round.
+fn removal_rounds(grid: &mut Grid<char>, rule: &Rule) -> Vec<Vec<(usize, usize)>> {
+    // Removing a roll only ever lowers its neighbours' counts, so rather than
+    // rescanning the grid each round we keep a worklist of rolls that have
+    // become accessible and only revisit the cells around each removal.
+    // Custom neighbourhoods need not be symmetric, so the cells whose count
+    // includes a removed roll are found through the reversed offsets.
+    // The queue stays ordered by round, so a roll freed up by a removal in
+    // round `k` is exactly one the round-by-round rescan would take in `k + 1`.
+    // Each roll is queued once: either at the start, or when a removal takes
+    // its count from `threshold` to just below it. Counts only ever drop one
+    // at a time, so that crossing can't be skipped. Counts are bounded by the
+    // number of offsets and queue entries hold a flat cell index and round,
+    // which keeps the working set to a few bytes per cell on large grids.
+    let reversed: Vec<(isize, isize)> = rule.offsets.iter().map(|&(dr, dc)| (-dr, -dc)).collect();
+    let width = grid.width();
+    assert!(
+        grid.width() * grid.height() <= u32::MAX as usize,
+        "Grid too large to index with u32"
+    );
+    let mut counts = Grid::new(grid.width(), grid.height(), 0u32);
+    let mut queue: VecDeque<(u32, u32)> = VecDeque::new();
+
+    for (r, c) in grid.positions() {
+        if grid[(r, c)] == rule.roll {
+            let count = rule.count_neighbors(grid, r, c);
+            counts[(r, c)] = count as u32;
+            if count < rule.threshold {
+                queue.push_back(((r * width + c) as u32, 0));
             }
         }
     }
 
-    Ok(accessible_rolls)
+    let mut rounds: Vec<Vec<(usize, usize)>> = Vec::new();
+
+    while let Some((idx, round)) = queue.pop_front() {
+        let (r, c) = (idx as usize / width, idx as usize % width);
+        let round = round as usize;
+        grid[(r, c)] = rule.empty;
+        if round == rounds.len() {
+            rounds.push(Vec::new());
+        }
+        rounds[round].push((r, c));
+
+        rule.for_each_neighbor(grid, r, c, &reversed, |pos| {
+            if grid[pos] == rule.roll {
+                counts[pos] -= 1;
+                if counts[pos] as usize + 1 == rule.threshold {
+                    queue.push_back(((pos.0 * width + pos.1) as u32, round as u32 + 1));
+                }
+            }
+        });
+    }
+
+    rounds
 }
 
-fn part_two(filename: &str) -> io::Result<usize> {
-    let mut grid = read_grid(filename)?;
+fn part_two(filename: &str, rule: &Rule) -> io::Result<usize> {
+    let mut grid = read_grid(filename, rule.empty)?;
     if grid.is_empty() {
         return Ok(0);
     }
 
-    let rows = grid.len();
-    let cols = grid[0].len();
-    let mut total_removed = 0;
+    let rounds = removal_rounds(&mut grid, rule);
+    Ok(rounds.iter().map(|removed| removed.len()).sum())
+}
 
-    loop {
-        let mut to_remove = Vec::new();
+/// One bit per cell, set where there is a roll. Rows are padded to whole
+/// `u64` words so that a row's neighbour counts can be computed a word at a
+/// time with shifts and a bit-sliced adder instead of per-cell branches.
+struct BitGrid {
+    height: usize,
+    words: usize,
+    bits: Vec<u64>,
+}
+
+impl BitGrid {
+    fn from_grid(grid: &Grid<char>, roll: char) -> Self {
+        let words = grid.width().div_ceil(64);
+        let mut bits = vec![0u64; words * grid.height()];
+        for (r, c) in grid.positions() {
+            if grid[(r, c)] == roll {
+                bits[r * words + c / 64] |= 1 << (c % 64);
+            }
+        }
+        BitGrid {
+            height: grid.height(),
+            words,
+            bits,
+        }
+    }
+
+    fn row(&self, r: usize) -> &[u64] {
+        &self.bits[r * self.words..(r + 1) * self.words]
+    }
+
+    /// Writes `row` shifted so that bit `c` of `out` holds the cell at column `c + dc`.
+    fn shift_row(row: &[u64], dc: isize, out: &mut [u64]) {
+        let words = row.len() as isize;
+        let word_shift = dc.div_euclid(64);
+        let bit_shift = dc.rem_euclid(64) as u32;
+        let word = |i: isize| {
+            if i >= 0 && i < words {
+                row[i as usize]
+            } else {
+                0
+            }
+        };
+        for (i, out) in out.iter_mut().enumerate() {
+            let lo = word(i as isize + word_shift);
+            let hi = word(i as isize + word_shift + 1);
+            *out = if bit_shift == 0 {
+                lo
+            } else {
+                (lo >> bit_shift) | (hi << (64 - bit_shift))
+            };
+        }
+    }
 
-        for r in 0..rows {
-            for c in 0..cols {
-                if grid[r][c] == '@' {
-                    if count_neighbors(&grid, r, c) < 4 {
-                        to_remove.push((r, c));
+    /// Returns the rolls in each row that have fewer than `rule.threshold` rolls
+    /// among their neighbours.
+    fn accessible(&self, rule: &Rule) -> Vec<u64> {
+        let planes_needed = (usize::BITS - rule.offsets.len().leading_zeros()) as usize;
+        let mut planes = vec![vec![0u64; self.words]; planes_needed];
+        let mut shifted = vec![0u64; self.words];
+        let mut result = vec![0u64; self.bits.len()];
+
+        for r in 0..self.height {
+            for plane in planes.iter_mut() {
+                plane.fill(0);
+            }
+
+            for &(dr, dc) in &rule.offsets {
+                let nr = r as isize + dr;
+                if nr < 0 || nr >= self.height as isize {
+                    continue;
+                }
+                BitGrid::shift_row(self.row(nr as usize), dc, &mut shifted);
+
+                // Add the shifted row into the per-cell counters, one bit plane at a time.
+                for w in 0..self.words {
+                    let mut carry = shifted[w];
+                    for plane in planes.iter_mut() {
+                        let next = plane[w] & carry;
+                        plane[w] ^= carry;
+                        carry = next;
                     }
                 }
             }
+
+            // Bit-sliced `count < threshold`, comparing from the most significant plane down.
+            let row = self.row(r);
+            for w in 0..self.words {
+                let mut less = 0u64;
+                let mut equal = !0u64;
+                if rule.threshold >> planes_needed != 0 {
+                    less = !0;
+                } else {
+                    for (i, plane) in planes.iter().enumerate().rev() {
+                        if rule.threshold >> i & 1 == 1 {
+                            less |= equal & !plane[w];
+                            equal &= plane[w];
+                        } else {
+                            equal &= !plane[w];
+                        }
+                    }
+                }
+                result[r * self.words + w] = row[w] & less;
+            }
         }
 
-        if to_remove.is_empty() {
-            break;
+        result
+    }
+
+    fn remove(&mut self, mask: &[u64]) {
+        for (word, &m) in self.bits.iter_mut().zip(mask) {
+            *word &= !m;
         }
+    }
+}
+
+fn count_bits(words: &[u64]) -> usize {
+    words.iter().map(|w| w.count_ones() as usize).sum()
+}
 
-        total_removed += to_remove.len();
-        for (r, c) in to_remove {
-            grid[r][c] = '.';
+fn part_one_bitset(filename: &str, rule: &Rule) -> io::Result<usize> {
+    let grid = read_grid(filename, rule.empty)?;
+    if grid.is_empty() {
+        return Ok(0);
+    }
+
+    let bits = BitGrid::from_grid(&grid, rule.roll);
+    Ok(count_bits(&bits.accessible(rule)))
+}
+
+fn part_two_bitset(filename: &str, rule: &Rule) -> io::Result<usize> {
+    let grid = read_grid(filename, rule.empty)?;
+    if grid.is_empty() {
+        return Ok(0);
+    }
+
+    let mut bits = BitGrid::from_grid(&grid, rule.roll);
+    let mut total_removed = 0;
+
+    loop {
+        let accessible = bits.accessible(rule);
+        let removed = count_bits(&accessible);
+        if removed == 0 {
+            break;
         }
+        total_removed += removed;
+        bits.remove(&accessible);
     }
 
     Ok(total_removed)
 }
 
+fn validate_bitset(options: &Options) -> io::Result<()> {
+    let (input_file, rule) = (options.input_file.as_str(), &options.rule);
+    let chars = (part_one(input_file, rule)?, part_two(input_file, rule)?);
+    let bits = (
+        part_one_bitset(input_file, rule)?,
+        part_two_bitset(input_file, rule)?,
+    );
+
+    if chars == bits {
+        println!("Bitset check: OK");
+    } else {
+        println!(
+            "Bitset check: MISMATCH (chars: {:?}, bitset: {:?})",
+            chars, bits
+        );
+    }
+    Ok(())
+}
+
+fn print_round_stats(grid: &Grid<char>, rounds: &[Vec<(usize, usize)>], rule: &Rule) {
+    let mut remaining = grid
+        .positions()
+        .filter(|&pos| grid[pos] == rule.roll)
+        .count();
+
+    println!("{:>5} {:>8} {:>10}", "Round", "Removed", "Remaining");
+    println!("{:>5} {:>8} {:>10}", 0, 0, remaining);
+    for (i, removed) in rounds.iter().enumerate() {
+        remaining -= removed.len();
+        println!("{:>5} {:>8} {:>10}", i + 1, removed.len(), remaining);
+    }
+}
+
+fn write_ppm(
+    path: &Path,
+    grid: &Grid<char>,
+    removed: &[(usize, usize)],
+    rule: &Rule,
+) -> io::Result<()> {
+    let mut just_removed = Grid::new(grid.width(), grid.height(), false);
+    for &pos in removed {
+        just_removed[pos] = true;
+    }
+
+    let mut data = format!("P6\n{} {}\n255\n", grid.width(), grid.height()).into_bytes();
+    for pos in grid.positions() {
+        let rgb: [u8; 3] = if just_removed[pos] {
+            [220, 50, 47]
+        } else if grid[pos] == rule.roll {
+            [40, 40, 40]
+        } else if grid[pos] == rule.empty {
+            [255, 255, 255]
+        } else {
+            [180, 180, 180]
+        };
+        data.extend_from_slice(&rgb);
+    }
+    fs::write(path, data)
+}
+
+/// Writes the grid before the first round and after each round, marking the
+/// rolls removed in that round with `'x'`.
+fn write_frames(
+    dir: &str,
+    grid: &Grid<char>,
+    rounds: &[Vec<(usize, usize)>],
+    rule: &Rule,
+    ppm: bool,
+) -> io::Result<()> {
+    fs::create_dir_all(dir)?;
+    let dir = Path::new(dir);
+    let mut grid = grid.clone();
+
+    fs::write(dir.join("round_0000.txt"), grid.to_string())?;
+    if ppm {
+        write_ppm(&dir.join("round_0000.ppm"), &grid, &[], rule)?;
+    }
+
+    for (i, removed) in rounds.iter().enumerate() {
+        let mut frame = grid.clone();
+        for &pos in removed {
+            grid[pos] = rule.empty;
+            frame[pos] = 'x';
+        }
+        fs::write(
+            dir.join(format!("round_{:04}.txt", i + 1)),
+            frame.to_string(),
+        )?;
+        if ppm {
+            write_ppm(
+                &dir.join(format!("round_{:04}.ppm", i + 1)),
+                &grid,
+                removed,
+                rule,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+fn animate(options: &Options) -> io::Result<()> {
+    let rule = &options.rule;
+    let initial = read_grid(&options.input_file, rule.empty)?;
+    let mut grid = initial.clone();
+    let rounds = removal_rounds(&mut grid, rule);
+
+    if options.stats {
+        print_round_stats(&initial, &rounds, rule);
+    }
+    if let Some(dir) = &options.frames_dir {
+        write_frames(dir, &initial, &rounds, rule, options.ppm)?;
+        println!("Wrote {} frames to {}", rounds.len() + 1, dir);
+    }
+    Ok(())
+}
+
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
-    let input_file = if args.len() > 1 {
-        &args[1]
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return Ok(());
+        }
+    };
+    let input_file = options.input_file.as_str();
+    let rule = &options.rule;
+    let (part_one, part_two): (Solver, Solver) = if options.bitset {
+        (part_one_bitset, part_two_bitset)
     } else {
-        DEFAULT_INPUT
+        (part_one, part_two)
     };
 
     println!("Reading from: {}", input_file);
 
     // Part 1
-    match part_one(input_file) {
+    match part_one(input_file, rule) {
         Ok(p1) => {
             println!("Part 1: {}", p1);
 
             // Part 2
-            match part_two(input_file) {
+            match part_two(input_file, rule) {
                 Ok(p2) => {
                     println!("Part 2: {}", p2);
 
@@ -132,5 +590,240 @@ fn main() -> io::Result<()> {
         Err(e) => eprintln!("Error running Part 1: {}", e),
     }
 
+    if options.validate {
+        if let Err(e) = validate_bitset(&options) {
+            eprintln!("Error validating bitset representation: {}", e);
+        }
+    }
+
+    if options.stats || options.frames_dir.is_some() {
+        if let Err(e) = animate(&options) {
+            eprintln!("Error writing round statistics: {}", e);
+        }
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+..@@.@@@@.
+@@@.@.@.@@
+@@@@@.@.@@
+@.@@@@..@.
+@@.@@@@.@@
+.@@@@@@@.@
+.@.@.@.@@@
+@.@@@.@@@@
+.@@@@@@@@.
+@.@.@@@.@.";
+
+    /// The original approach: rescan the whole grid each round and remove
+    /// every accessible roll at once.
+    fn rescan_rounds(mut grid: Grid<char>, rule: &Rule) -> Vec<usize> {
+        let mut rounds = Vec::new();
+        loop {
+            let removable: Vec<(usize, usize)> = grid
+                .positions()
+                .filter(|&(r, c)| rule.is_accessible(&grid, r, c))
+                .collect();
+            if removable.is_empty() {
+                return rounds;
+            }
+            for &pos in &removable {
+                grid[pos] = rule.empty;
+            }
+            rounds.push(removable.len());
+        }
+    }
+
+    fn worklist_rounds(mut grid: Grid<char>, rule: &Rule) -> Vec<usize> {
+        removal_rounds(&mut grid, rule)
+            .iter()
+            .map(|removed| removed.len())
+            .collect()
+    }
+
+    fn random_grid(seed: u64, width: usize, height: usize) -> Grid<char> {
+        let mut state = seed | 1;
+        let rows = (0..height)
+            .map(|_| {
+                (0..width)
+                    .map(|_| {
+                        state ^= state << 13;
+                        state ^= state >> 7;
+                        state ^= state << 17;
+                        if state % 10 < 7 {
+                            '@'
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect()
+            })
+            .collect();
+        Grid::from_rows(rows, '.')
+    }
+
+    #[test]
+    fn sample_rounds_match_rescan() {
+        let grid = Grid::from_lines(SAMPLE.lines(), '.');
+        let rule = Rule::default();
+        let rounds = worklist_rounds(grid.clone(), &rule);
+        assert_eq!(rounds.iter().sum::<usize>(), 43);
+        assert_eq!(rounds, rescan_rounds(grid, &rule));
+    }
+
+    #[test]
+    fn random_rounds_match_rescan() {
+        for seed in 1..=20 {
+            let grid = random_grid(seed, 5 + seed as usize * 3, 40 - seed as usize);
+            let rule = Rule::default();
+            assert_eq!(
+                worklist_rounds(grid.clone(), &rule),
+                rescan_rounds(grid, &rule),
+                "seed {}",
+                seed
+            );
+        }
+    }
+
+    #[test]
+    fn random_rounds_match_rescan_with_other_rules() {
+        let rules = [
+            Rule {
+                threshold: 3,
+                offsets: Neighbourhood::VonNeumann(2).offsets(),
+                ..Rule::default()
+            },
+            Rule {
+                offsets: Neighbourhood::Custom(vec![(0, 1), (1, 1), (-2, 0)]).offsets(),
+                threshold: 2,
+                ..Rule::default()
+            },
+            Rule {
+                wrap: true,
+                ..Rule::default()
+            },
+        ];
+        for (i, rule) in rules.iter().enumerate() {
+            for seed in 1..=5 {
+                let grid = random_grid(seed * 31 + i as u64, 17, 23);
+                assert_eq!(
+                    worklist_rounds(grid.clone(), rule),
+                    rescan_rounds(grid, rule),
+                    "rule {} seed {}",
+                    i,
+                    seed
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn neighbourhood_parsing() {
+        let parse = |name: &str, radius| Neighbourhood::parse(name, radius).map(|n| n.offsets());
+        assert_eq!(parse("moore", None).unwrap().len(), 8);
+        assert_eq!(parse("von-neumann", Some(2)).unwrap().len(), 12);
+        assert_eq!(parse("0,1; -1,0", None).unwrap(), vec![(0, 1), (-1, 0)]);
+        assert_eq!(
+            parse("foo", None).unwrap_err(),
+            "Unknown neighbourhood: foo"
+        );
+        assert_eq!(parse(";;", None).unwrap_err(), "Unknown neighbourhood: ;;");
+        assert!(parse("0,1", Some(2)).is_err());
+        assert!(parse("0,x", None).is_err());
+    }
+
+    fn bitset_rounds(grid: &Grid<char>, rule: &Rule) -> Vec<usize> {
+        let mut bits = BitGrid::from_grid(grid, rule.roll);
+        let mut rounds = Vec::new();
+        loop {
+            let accessible = bits.accessible(rule);
+            match count_bits(&accessible) {
+                0 => return rounds,
+                removed => rounds.push(removed),
+            }
+            bits.remove(&accessible);
+        }
+    }
+
+    fn wide_rules() -> Vec<Rule> {
+        vec![
+            Rule::default(),
+            Rule {
+                threshold: 9,
+                offsets: Neighbourhood::Moore(2).offsets(),
+                ..Rule::default()
+            },
+            Rule {
+                threshold: 3,
+                offsets: vec![(0, 64), (0, -64), (1, 65), (-1, -70), (2, 130), (0, -1)],
+                ..Rule::default()
+            },
+            // Thresholds beyond what the counters can hold take the shortcut
+            // that makes every roll accessible.
+            Rule {
+                threshold: 16,
+                ..Rule::default()
+            },
+            Rule {
+                threshold: 4,
+                offsets: vec![(0, 64), (-1, -64), (1, 127)],
+                ..Rule::default()
+            },
+        ]
+    }
+
+    #[test]
+    fn shift_row_matches_per_bit_shift() {
+        let grid = random_grid(7, 190, 1);
+        let bits = BitGrid::from_grid(&grid, '@');
+        let row = bits.row(0);
+        let mut out = vec![0u64; row.len()];
+        for dc in -200..=200isize {
+            BitGrid::shift_row(row, dc, &mut out);
+            for c in 0..row.len() * 64 {
+                let src = c as isize + dc;
+                let expected = (0..190).contains(&src) && grid[(0, src as usize)] == '@';
+                assert_eq!(
+                    out[c / 64] >> (c % 64) & 1 == 1,
+                    expected,
+                    "dc {}, c {}",
+                    dc,
+                    c
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bitset_matches_char_grid_on_wide_grids() {
+        for (i, rule) in wide_rules().iter().enumerate() {
+            for seed in 1..=6 {
+                let grid = random_grid(seed * 31 + i as u64, 65 + seed as usize * 29, 12);
+                let expected: Vec<(usize, usize)> = grid
+                    .positions()
+                    .filter(|&(r, c)| rule.is_accessible(&grid, r, c))
+                    .collect();
+                let mask = BitGrid::from_grid(&grid, rule.roll).accessible(rule);
+                let words = grid.width().div_ceil(64);
+                let actual: Vec<(usize, usize)> = grid
+                    .positions()
+                    .filter(|&(r, c)| mask[r * words + c / 64] >> (c % 64) & 1 == 1)
+                    .collect();
+                assert_eq!(actual, expected, "rule {}, seed {}", i, seed);
+                assert_eq!(
+                    bitset_rounds(&grid, rule),
+                    worklist_rounds(grid.clone(), rule),
+                    "rule {}, seed {}",
+                    i,
+                    seed
+                );
+            }
+        }
+    }
+}
@@ -1,6 +1,9 @@
 //! https://adventofcode.com/2025/day/5
-use std::cmp;
+mod range_set;
+
+use range_set::{Range, RangeSet};
 use std::env;
+use std::fmt;
 use std::fs;
 use std::path::Path;
 use std::str::FromStr;
@@ -8,108 +11,272 @@ use std::str::FromStr;
 const DEFAULT_INPUT: &str = "../input/day05.txt";
 const DEFAULT_OUTPUT: &str = "output/day05.txt";
 
-#[derive(Debug, Clone, Copy)]
-struct Range {
-    start: i64,
-    end: i64,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Section {
+    Ranges,
+    Ids,
+}
+
+impl fmt::Display for Section {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Section::Ranges => write!(f, "ranges"),
+            Section::Ids => write!(f, "IDs"),
+        }
+    }
 }
 
 #[derive(Debug)]
-enum InputEntry {
-    Range(Range),
-    Id(i64),
+struct ParseError {
+    line: usize,
+    section: Section,
+    message: String,
 }
 
-impl FromStr for InputEntry {
-    type Err = String;
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {} ({} section): {}",
+            self.line, self.section, self.message
+        )
+    }
+}
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let s = s.trim();
-        if s.is_empty() {
-            return Err("Empty line".to_string());
-        }
+/// The puzzle input: a block of fresh ID ranges, a blank line, then a block of
+/// available ingredient IDs. `#` starts a comment that runs to the end of the line.
+#[derive(Debug, Default)]
+struct Inventory {
+    ranges: Vec<Range>,
+    ids: Vec<i64>,
+}
 
-        if let Some(pos) = s.find('-') {
-            let start = s[..pos].parse::<i64>().map_err(|_| "Invalid start")?;
-            let end = s[pos + 1..].parse::<i64>().map_err(|_| "Invalid end")?;
-            Ok(InputEntry::Range(Range { start, end }))
-        } else {
-            let id = s.parse::<i64>().map_err(|_| "Invalid ID")?;
-            Ok(InputEntry::Id(id))
-        }
-    }
+/// Splits `start-end` on the first dash that is not a leading minus sign.
+fn split_range(s: &str) -> Option<(&str, &str)> {
+    let pos = s.get(1..)?.find('-')? + 1;
+    Some((&s[..pos], &s[pos + 1..]))
 }
 
-fn part_one(entries: &[InputEntry]) -> i64 {
-    let mut ranges = Vec::new();
-    let mut ids = Vec::new();
+fn parse_range(s: &str) -> Result<Range, String> {
+    if let Ok(id) = s.parse::<i64>() {
+        return Err(format!("Unexpected ID {} (missing blank line?)", id));
+    }
+    let (start, end) = split_range(s).ok_or_else(|| format!("Invalid range: {}", s))?;
+    let start = start
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| format!("Invalid start: {}", start))?;
+    let end = end
+        .trim()
+        .parse::<i64>()
+        .map_err(|_| format!("Invalid end: {}", end))?;
+    Range::new(start, end)
+}
 
-    for entry in entries {
-        match entry {
-            InputEntry::Range(r) => ranges.push(r),
-            InputEntry::Id(id) => ids.push(*id),
-        }
+fn parse_id(s: &str) -> Result<i64, String> {
+    if split_range(s).is_some() {
+        return Err(format!("Unexpected range {}", s));
     }
+    s.parse::<i64>().map_err(|_| format!("Invalid ID: {}", s))
+}
+
+impl FromStr for Inventory {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut inventory = Inventory::default();
+        let mut section = Section::Ranges;
 
-    let mut fresh_count = 0;
-    for id in ids {
-        for r in &ranges {
-            if id >= r.start && id <= r.end {
-                fresh_count += 1;
-                break;
+        for (i, raw) in s.lines().enumerate() {
+            let is_comment = raw.trim_start().starts_with('#');
+            let line = raw.split('#').next().unwrap_or("").trim();
+
+            if line.is_empty() {
+                // Only a truly blank line separates the sections; blank lines
+                // before the first range and comment-only lines are skipped.
+                if !is_comment && section == Section::Ranges && !inventory.ranges.is_empty() {
+                    section = Section::Ids;
+                }
+                continue;
             }
+
+            let result = match section {
+                Section::Ranges => parse_range(line).map(|r| inventory.ranges.push(r)),
+                Section::Ids => parse_id(line).map(|id| inventory.ids.push(id)),
+            };
+            result.map_err(|message| ParseError {
+                line: i + 1,
+                section,
+                message,
+            })?;
         }
+
+        Ok(inventory)
     }
-    fresh_count
 }
 
-fn part_two(entries: &[InputEntry]) -> i64 {
-    let mut ranges: Vec<Range> = entries
+fn part_one(inventory: &Inventory) -> i64 {
+    let fresh = RangeSet::from_ranges(inventory.ranges.iter().copied());
+
+    inventory
+        .ids
         .iter()
-        .filter_map(|e| match e {
-            InputEntry::Range(r) => Some(*r),
-            _ => None,
+        .filter(|&&id| fresh.contains(id))
+        .count() as i64
+}
+
+fn part_two(inventory: &Inventory) -> u128 {
+    RangeSet::from_ranges(inventory.ranges.iter().copied()).len()
+}
+
+struct IdReport {
+    id: i64,
+    merged: Option<Range>,
+    originals: Vec<Range>,
+}
+
+/// Classifies every ID as fresh or spoiled, recording the merged range that
+/// contains it and every original range from the input that does.
+fn build_report(inventory: &Inventory) -> (Vec<IdReport>, RangeSet) {
+    let fresh = RangeSet::from_ranges(inventory.ranges.iter().copied());
+
+    // Every original range lies inside exactly one merged range, so grouping
+    // them that way means an ID only needs to look at its own merged range's
+    // originals, and a spoiled ID at none.
+    let mut originals = inventory.ranges.clone();
+    originals.sort_by_key(|r| r.start());
+    let mut by_merged: Vec<Vec<Range>> = vec![Vec::new(); fresh.ranges().len()];
+    for r in originals {
+        if let Some(idx) = fresh.find(r.start()) {
+            by_merged[idx].push(r);
+        }
+    }
+
+    let reports = inventory
+        .ids
+        .iter()
+        .map(|&id| {
+            let idx = fresh.find(id);
+            let originals = match idx {
+                Some(idx) => {
+                    let group = &by_merged[idx];
+                    let candidates = group.partition_point(|r| r.start() <= id);
+                    group[..candidates]
+                        .iter()
+                        .filter(|r| r.contains(id))
+                        .copied()
+                        .collect()
+                }
+                None => Vec::new(),
+            };
+            IdReport {
+                id,
+                merged: idx.map(|idx| fresh.ranges()[idx]),
+                originals,
+            }
         })
         .collect();
 
-    if ranges.is_empty() {
-        return 0;
+    (reports, fresh)
+}
+
+fn join_ranges(ranges: &[Range], sep: &str) -> String {
+    ranges
+        .iter()
+        .map(|r| r.to_string())
+        .collect::<Vec<_>>()
+        .join(sep)
+}
+
+fn print_report(reports: &[IdReport], fresh: &RangeSet) {
+    println!(
+        "{:>15}  {:<8} {:<25} Original ranges",
+        "ID", "Status", "Merged range"
+    );
+    for report in reports {
+        let (status, merged) = match report.merged {
+            Some(r) => ("fresh", r.to_string()),
+            None => ("spoiled", "-".to_string()),
+        };
+        println!(
+            "{:>15}  {:<8} {:<25} {}",
+            report.id,
+            status,
+            merged,
+            join_ranges(&report.originals, ", ")
+        );
     }
 
-    ranges.sort_by_key(|r| r.start);
+    let spoiled: Vec<String> = reports
+        .iter()
+        .filter(|r| r.merged.is_none())
+        .map(|r| r.id.to_string())
+        .collect();
+    println!("Spoiled IDs ({}): {}", spoiled.len(), spoiled.join(", "));
 
-    let mut merged = Vec::new();
-    merged.push(ranges[0]);
+    let gaps = fresh.gaps();
+    println!("Gaps ({}): {}", gaps.len(), join_ranges(&gaps, ", "));
+}
 
-    for r in ranges.iter().skip(1) {
-        let last_idx = merged.len() - 1;
-        let last = &mut merged[last_idx];
+fn write_report_csv(dir: &str, reports: &[IdReport], fresh: &RangeSet) -> std::io::Result<()> {
+    fs::create_dir_all(dir)?;
 
-        if r.start <= last.end {
-            last.end = cmp::max(last.end, r.end);
-        } else {
-            merged.push(*r);
-        }
-    }
-  
-    let mut total_fresh: i64 = 0;
-    for r in merged {
-        total_fresh += r.end - r.start + 1;
+    let mut ids = String::from("id,status,merged_range,original_ranges\n");
+    for report in reports {
+        let (status, merged) = match report.merged {
+            Some(r) => ("fresh", r.to_string()),
+            None => ("spoiled", String::new()),
+        };
+        ids.push_str(&format!(
+            "{},{},{},{}\n",
+            report.id,
+            status,
+            merged,
+            join_ranges(&report.originals, ";")
+        ));
     }
+    fs::write(Path::new(dir).join("day05_ids.csv"), ids)?;
 
-    total_fresh
+    let mut gaps = String::from("start,end,length\n");
+    for gap in fresh.gaps() {
+        gaps.push_str(&format!("{},{},{}\n", gap.start(), gap.end(), gap.len()));
+    }
+    fs::write(Path::new(dir).join("day05_gaps.csv"), gaps)
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let input_path = args.get(1).map(|s| s.as_str()).unwrap_or(DEFAULT_INPUT);
+    let mut input_path = DEFAULT_INPUT;
+    let mut report = false;
+    let mut csv_dir = None;
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--report" => report = true,
+            "--csv" => match iter.next() {
+                Some(dir) => csv_dir = Some(dir.as_str()),
+                None => {
+                    eprintln!("Missing directory for --csv");
+                    return;
+                }
+            },
+            _ => input_path = arg.as_str(),
+        }
+    }
     println!("Reading from: {}", input_path);
 
     let content = fs::read_to_string(input_path).expect("Could not read input file");
-    let entries: Vec<InputEntry> = content.lines().filter_map(|l| l.parse().ok()).collect();
+    let inventory: Inventory = match content.parse() {
+        Ok(inventory) => inventory,
+        Err(e) => {
+            eprintln!("Error parsing {}: {}", input_path, e);
+            return;
+        }
+    };
 
-    let p1 = part_one(&entries);
-    let p2 = part_two(&entries);
+    let p1 = part_one(&inventory);
+    let p2 = part_two(&inventory);
 
     println!("Part 1: {}", p1);
     println!("Part 2: {}", p2);
@@ -120,4 +287,106 @@ fn main() {
     fs::write(DEFAULT_OUTPUT, format!("{}\n{}\n", p1, p2)).expect("Could not write output file");
 
     println!("Results saved to {}", DEFAULT_OUTPUT);
+
+    if report || csv_dir.is_some() {
+        let (reports, fresh) = build_report(&inventory);
+        if report {
+            print_report(&reports, &fresh);
+        }
+        if let Some(dir) = csv_dir {
+            match write_report_csv(dir, &reports, &fresh) {
+                Ok(()) => println!("Report CSVs saved to {}", dir),
+                Err(e) => eprintln!("Error writing report CSVs: {}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bounds(inventory: &Inventory) -> Vec<(i64, i64)> {
+        inventory
+            .ranges
+            .iter()
+            .map(|r| (r.start(), r.end()))
+            .collect()
+    }
+
+    fn parse_error(s: &str) -> String {
+        s.parse::<Inventory>().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn sample_parses() {
+        let inventory: Inventory = "3-5\n10-14\n16-20\n12-18\n\n1\n5\n8\n11\n17\n32"
+            .parse()
+            .unwrap();
+        assert_eq!(
+            bounds(&inventory),
+            vec![(3, 5), (10, 14), (16, 20), (12, 18)]
+        );
+        assert_eq!(inventory.ids, vec![1, 5, 8, 11, 17, 32]);
+        assert_eq!(part_one(&inventory), 3);
+        assert_eq!(part_two(&inventory), 14);
+    }
+
+    #[test]
+    fn comment_only_line_does_not_separate_sections() {
+        let inventory: Inventory =
+            "# fresh ranges\n3-5\n  # more below\n10-14 # trailing\n\n# IDs\n4\n12"
+                .parse()
+                .unwrap();
+        assert_eq!(bounds(&inventory), vec![(3, 5), (10, 14)]);
+        assert_eq!(inventory.ids, vec![4, 12]);
+    }
+
+    #[test]
+    fn blank_lines_before_the_first_range_are_skipped() {
+        let inventory: Inventory = "\n\n3-5\n\n4".parse().unwrap();
+        assert_eq!(bounds(&inventory), vec![(3, 5)]);
+        assert_eq!(inventory.ids, vec![4]);
+    }
+
+    #[test]
+    fn negative_ids_and_bounds_are_accepted() {
+        let inventory: Inventory = "-10--5\n-3-4\n\n-7\n-4\n0".parse().unwrap();
+        assert_eq!(bounds(&inventory), vec![(-10, -5), (-3, 4)]);
+        assert_eq!(inventory.ids, vec![-7, -4, 0]);
+        assert_eq!(part_one(&inventory), 2);
+    }
+
+    #[test]
+    fn id_in_the_ranges_section_is_reported() {
+        assert_eq!(
+            parse_error("3-5\n7\n\n4"),
+            "line 2 (ranges section): Unexpected ID 7 (missing blank line?)"
+        );
+        assert_eq!(
+            parse_error("3-5\n-7"),
+            "line 2 (ranges section): Unexpected ID -7 (missing blank line?)"
+        );
+    }
+
+    #[test]
+    fn range_in_the_ids_section_is_rejected() {
+        assert_eq!(
+            parse_error("3-5\n\n4\n# comment\n6-8"),
+            "line 5 (IDs section): Unexpected range 6-8"
+        );
+    }
+
+    #[test]
+    fn errors_name_their_line_and_section() {
+        assert_eq!(
+            parse_error("# header\n3-x"),
+            "line 2 (ranges section): Invalid end: x"
+        );
+        assert_eq!(
+            parse_error("3-5\n\n4\nabc"),
+            "line 4 (IDs section): Invalid ID: abc"
+        );
+        assert!(parse_error("\n5-3").starts_with("line 2 (ranges section): "));
+    }
 }
@@ -1,148 +1,509 @@
+mod grid;
+
+use grid::Grid;
 use std::env;
+use std::fmt;
 use std::fs;
+use std::io;
+use std::ops::Range;
 use std::path::Path;
 
 const DEFAULT_INPUT: &str = "../input/day06.txt";
 const DEFAULT_OUTPUT: &str = "output/day06.txt";
 
-fn solve_part1(grid: &Vec<Vec<char>>, start_col: usize, end_col: usize) -> i64 {
-    let mut s = String::new();
-    for row in grid {
-        for col in start_col..end_col {
-            s.push(row[col]);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Pow,
+    Min,
+    Max,
+}
+
+impl Operator {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "+" => Some(Operator::Add),
+            "-" => Some(Operator::Sub),
+            "*" => Some(Operator::Mul),
+            "/" => Some(Operator::Div),
+            "^" => Some(Operator::Pow),
+            "min" => Some(Operator::Min),
+            "max" => Some(Operator::Max),
+            _ => None,
         }
-        s.push(' ');
     }
 
-    let tokens: Vec<&str> = s.split_whitespace().collect();
-    if tokens.len() < 2 {
-        return 0;
+    /// Applies the operator with overflow checking. Division truncates towards zero.
+    fn apply(self, a: i64, b: i64) -> Result<i64, String> {
+        let result = match self {
+            Operator::Add => a.checked_add(b),
+            Operator::Sub => a.checked_sub(b),
+            Operator::Mul => a.checked_mul(b),
+            Operator::Div if b == 0 => return Err(format!("division by zero in {} / 0", a)),
+            Operator::Div => a.checked_div(b),
+            Operator::Pow => {
+                let exp =
+                    u32::try_from(b).map_err(|_| format!("invalid exponent in {} ^ {}", a, b))?;
+                a.checked_pow(exp)
+            }
+            Operator::Min => Some(a.min(b)),
+            Operator::Max => Some(a.max(b)),
+        };
+        result.ok_or_else(|| format!("{} {} {} overflows i64", a, self, b))
     }
+}
 
-    let op_str = tokens.last().unwrap();
-    let op_char = op_str.chars().next().unwrap_or(' ');
-    if op_char != '+' && op_char != '*' {
-        return 0;
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let symbol = match self {
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Pow => "^",
+            Operator::Min => "min",
+            Operator::Max => "max",
+        };
+        write!(f, "{}", symbol)
     }
+}
 
-    let mut nums = Vec::new();
-    for i in 0..tokens.len() - 1 {
-        if let Ok(n) = tokens[i].parse::<i64>() {
-            nums.push(n);
-        }
-    }
+/// How the digits of a column block are read into operands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reading {
+    /// One operand per row, read left to right.
+    RowWise,
+    /// One operand per column, read top to bottom, columns taken right to left.
+    ColumnWise,
+}
 
-    if nums.is_empty() {
-        return 0;
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Problem {
+    operator: Operator,
+    operands: Vec<i64>,
+    column_span: Range<usize>,
+}
+
+impl Problem {
+    /// Folds the operands left to right, so `-` and `/` read as `a - b - c`.
+    fn solve(&self) -> Result<i64, WorksheetError> {
+        let mut operands = self.operands.iter().copied();
+        let first = operands.next().unwrap_or(0);
+        operands.try_fold(first, |acc, n| {
+            self.operator
+                .apply(acc, n)
+                .map_err(|message| WorksheetError {
+                    column_span: self.column_span.clone(),
+                    message,
+                })
+        })
     }
+}
 
-    let mut result = nums[0];
-    for &n in &nums[1..] {
-        match op_char {
-            '+' => result += n,
-            '*' => result *= n,
-            _ => {}
-        }
+#[derive(Debug)]
+struct WorksheetError {
+    column_span: Range<usize>,
+    message: String,
+}
+
+impl fmt::Display for WorksheetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "columns {}..{}: {}",
+            self.column_span.start, self.column_span.end, self.message
+        )
     }
-    result
 }
 
-fn solve_part2(grid: &Vec<Vec<char>>, start_col: usize, end_col: usize) -> i64 {
-    let mut nums = Vec::new();
-    let mut op = None;
+/// Read access to worksheet cells. Cells past the end of a short row read as spaces.
+trait Worksheet {
+    fn height(&self) -> usize;
+    fn cell(&self, r: usize, c: usize) -> char;
+}
 
-    for x in (start_col..end_col).rev() {
-        let mut num_str = String::new();
-        for row in grid {
-            let c = row[x];
-            if c.is_ascii_digit() {
-                num_str.push(c);
-            } else if c == '+' || c == '*' {
-                op = Some(c);
-            }
-        }
+impl Worksheet for Grid<char> {
+    fn height(&self) -> usize {
+        Grid::height(self)
+    }
 
-        if !num_str.is_empty() {
-            if let Ok(n) = num_str.parse::<i64>() {
-                nums.push(n);
-            }
-        }
+    fn cell(&self, r: usize, c: usize) -> char {
+        self.get(r, c).copied().unwrap_or(' ')
     }
+}
 
-    if nums.is_empty() || op.is_none() {
-        return 0;
+/// Unpadded rows borrowed straight from the input bytes.
+impl Worksheet for [&[u8]] {
+    fn height(&self) -> usize {
+        self.len()
     }
-    let op_char = op.unwrap();
 
-    let mut result = nums[0];
-    for &n in &nums[1..] {
-        match op_char {
-            '+' => result += n,
-            '*' => result *= n,
+    fn cell(&self, r: usize, c: usize) -> char {
+        self[r].get(c).map_or(' ', |&b| b as char)
+    }
+}
+
+/// Splits the worksheet into blocks of columns separated by all-space columns.
+fn column_blocks(grid: &Grid<char>) -> Vec<Range<usize>> {
+    let mut blocks = Vec::new();
+    let mut start_col: Option<usize> = None;
+
+    for x in 0..=grid.width() {
+        let empty = x == grid.width() || grid.column(x).all(|&c| c == ' ');
+
+        match (start_col, empty) {
+            (None, false) => start_col = Some(x),
+            (Some(start), true) => {
+                blocks.push(start..x);
+                start_col = None;
+            }
             _ => {}
         }
     }
-    result
+    blocks
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let input_path = if args.len() > 1 {
-        &args[1]
-    } else {
-        DEFAULT_INPUT
+/// Parses an optionally signed decimal number, failing on any other character or on overflow.
+fn parse_number<I: Iterator<Item = char>>(chars: I) -> Option<i64> {
+    let mut chars = chars.peekable();
+    let negative = match chars.peek() {
+        Some('-') => true,
+        Some('+') => false,
+        _ => return parse_digits(chars, false),
     };
-    println!("Reading from: {}", input_path);
+    chars.next();
+    parse_digits(chars, negative)
+}
 
-    let content = match fs::read_to_string(input_path) {
-        Ok(c) => c,
-        Err(e) => {
-            eprintln!("Error reading file: {}", e);
-            return;
-        }
+fn parse_digits<I: Iterator<Item = char>>(chars: I, negative: bool) -> Option<i64> {
+    let mut value: i64 = 0;
+    let mut any = false;
+    for ch in chars {
+        let digit = ch.to_digit(10)? as i64;
+        value = value.checked_mul(10)?;
+        value = if negative {
+            value.checked_sub(digit)?
+        } else {
+            value.checked_add(digit)?
+        };
+        any = true;
+    }
+    any.then_some(value)
+}
+
+/// Parses the worksheet into one problem per column block. The last row holds
+/// the operators and every row above it holds operand digits.
+fn parse_worksheet(grid: &Grid<char>, reading: Reading) -> Result<Vec<Problem>, WorksheetError> {
+    column_blocks(grid)
+        .into_iter()
+        .map(|span| parse_block(grid, span, reading))
+        .collect()
+}
+
+fn parse_block<W: Worksheet + ?Sized>(
+    sheet: &W,
+    span: Range<usize>,
+    reading: Reading,
+) -> Result<Problem, WorksheetError> {
+    let error = |message: String| WorksheetError {
+        column_span: span.clone(),
+        message,
     };
+    let text = |r: usize| -> String { span.clone().map(|c| sheet.cell(r, c)).collect() };
 
-    let lines: Vec<&str> = content.lines().collect();
-    if lines.is_empty() {
-        return;
+    let operator_row = sheet.height() - 1;
+    let operator_text = text(operator_row);
+    let operator_text = operator_text.trim();
+    if operator_text.is_empty() {
+        return Err(error("missing operator".to_string()));
     }
+    let operator = Operator::parse(operator_text)
+        .ok_or_else(|| error(format!("unknown operator '{}'", operator_text)))?;
 
-    let max_width = lines.iter().map(|l| l.len()).max().unwrap_or(0);
-
-    let mut grid: Vec<Vec<char>> = Vec::new();
-    for line in lines {
-        let mut row: Vec<char> = line.chars().collect();
-        while row.len() < max_width {
-            row.push(' ');
+    let mut operands = Vec::new();
+    match reading {
+        Reading::RowWise => {
+            for r in 0..operator_row {
+                // Find the single token in this row without building a string for it.
+                let mut tokens = 0;
+                let mut token = span.start..span.start;
+                let mut prev_space = true;
+                for c in span.clone() {
+                    let is_space = sheet.cell(r, c).is_whitespace();
+                    if !is_space {
+                        if prev_space {
+                            tokens += 1;
+                            token.start = c;
+                        }
+                        token.end = c + 1;
+                    }
+                    prev_space = is_space;
+                }
+                match tokens {
+                    0 => return Err(error(format!("ragged block: row {} has no operand", r + 1))),
+                    1 => operands.push(parse_number(token.map(|c| sheet.cell(r, c))).ok_or_else(
+                        || {
+                            error(format!(
+                                "invalid operand '{}' in row {}",
+                                text(r).trim(),
+                                r + 1
+                            ))
+                        },
+                    )?),
+                    n => {
+                        return Err(error(format!(
+                            "ragged block: row {} has {} operands",
+                            r + 1,
+                            n
+                        )))
+                    }
+                }
+            }
+        }
+        Reading::ColumnWise => {
+            for x in span.clone().rev() {
+                let digits = || {
+                    (0..operator_row)
+                        .map(move |r| sheet.cell(r, x))
+                        .filter(|c| !c.is_whitespace())
+                };
+                // Only the operator reaches into this column, e.g. under a wide `min`.
+                if digits().next().is_none() {
+                    continue;
+                }
+                operands.push(parse_number(digits()).ok_or_else(|| {
+                    let digits: String = digits().collect();
+                    error(format!("invalid operand '{}' in column {}", digits, x))
+                })?);
+            }
         }
-        grid.push(row);
     }
 
-    let mut total_p1: i64 = 0;
-    let mut total_p2: i64 = 0;
-    let mut start_col: Option<usize> = None;
+    if operands.is_empty() {
+        return Err(error("no operands".to_string()));
+    }
 
-    let is_empty = |col: usize, g: &Vec<Vec<char>>| -> bool { g.iter().all(|row| row[col] == ' ') };
+    Ok(Problem {
+        operator,
+        operands,
+        column_span: span,
+    })
+}
 
-    for x in 0..=max_width {
-        let empty = if x == max_width {
-            true
-        } else {
-            is_empty(x, &grid)
-        };
+fn grand_total<I>(problems: I, width: usize) -> Result<i64, WorksheetError>
+where
+    I: IntoIterator<Item = Result<Problem, WorksheetError>>,
+{
+    let mut total: i64 = 0;
+    for problem in problems {
+        let result = problem?.solve()?;
+        total = total.checked_add(result).ok_or_else(|| WorksheetError {
+            column_span: 0..width,
+            message: format!("grand total overflows i64 after adding {}", result),
+        })?;
+    }
+    Ok(total)
+}
 
-        match (start_col, empty) {
-            (None, false) => {
-                start_col = Some(x);
+fn solve(grid: &Grid<char>, reading: Reading) -> Result<i64, WorksheetError> {
+    grand_total(
+        parse_worksheet(grid, reading)?.into_iter().map(Ok),
+        grid.width(),
+    )
+}
+
+/// Finds the column blocks of unpadded rows with one pass over the bytes,
+/// setting a bit for every column that holds a non-space anywhere.
+fn byte_column_blocks(rows: &[&[u8]]) -> Vec<Range<usize>> {
+    let width = rows.iter().map(|row| row.len()).max().unwrap_or(0);
+    let mut occupied = vec![0u64; width.div_ceil(64)];
+    for row in rows {
+        for (c, &b) in row.iter().enumerate() {
+            if b != b' ' {
+                occupied[c / 64] |= 1 << (c % 64);
             }
+        }
+    }
+
+    let mut blocks = Vec::new();
+    let mut start_col: Option<usize> = None;
+    for x in 0..=width {
+        let empty = x == width || occupied[x / 64] >> (x % 64) & 1 == 0;
+        match (start_col, empty) {
+            (None, false) => start_col = Some(x),
             (Some(start), true) => {
-                total_p1 += solve_part1(&grid, start, x);
-                total_p2 += solve_part2(&grid, start, x);
+                blocks.push(start..x);
                 start_col = None;
             }
             _ => {}
         }
     }
+    blocks
+}
+
+/// Solves both readings straight from the file bytes, without building the
+/// padded char grid. The whole file is still held in memory: the rows are
+/// split in place, the column blocks are found up front, and the rows are
+/// then walked once per reading.
+fn solve_bytes(content: &[u8]) -> Result<(i64, i64), WorksheetError> {
+    let mut rows: Vec<&[u8]> = content
+        .split(|&b| b == b'\n')
+        .map(|row| row.strip_suffix(b"\r").unwrap_or(row))
+        .collect();
+    while rows
+        .last()
+        .is_some_and(|row| row.iter().all(|b| b.is_ascii_whitespace()))
+    {
+        rows.pop();
+    }
+    if rows.is_empty() {
+        return Ok((0, 0));
+    }
+
+    let blocks = byte_column_blocks(&rows);
+    let width = blocks.last().map_or(0, |b| b.end);
+    let rows = rows.as_slice();
+    let p1 = grand_total(
+        blocks
+            .iter()
+            .map(|span| parse_block(rows, span.clone(), Reading::RowWise)),
+        width,
+    )?;
+    let p2 = grand_total(
+        blocks
+            .iter()
+            .map(|span| parse_block(rows, span.clone(), Reading::ColumnWise)),
+        width,
+    )?;
+    Ok((p1, p2))
+}
+
+/// Lays out each column block side by side: the original worksheet text on
+/// top, then the operands, operator and result found by each reading.
+fn explain(grid: &Grid<char>) -> String {
+    let blocks = column_blocks(grid);
+    let readings = [("rows", Reading::RowWise), ("columns", Reading::ColumnWise)];
+
+    let mut labels = vec!["worksheet".to_string()];
+    labels.resize(grid.height(), String::new());
+    let mut cells: Vec<Vec<String>> = blocks
+        .iter()
+        .map(|span| {
+            grid.rows()
+                .map(|row| row[span.clone()].iter().collect())
+                .collect()
+        })
+        .collect();
+
+    for (name, reading) in readings {
+        let problems: Vec<Result<Problem, WorksheetError>> = blocks
+            .iter()
+            .map(|span| parse_block(grid, span.clone(), reading))
+            .collect();
+        let operand_lines = problems
+            .iter()
+            .map(|p| p.as_ref().map_or(1, |p| p.operands.len()))
+            .max()
+            .unwrap_or(0);
+
+        labels.push(String::new());
+        labels.push(name.to_string());
+        labels.resize(labels.len() + operand_lines, String::new());
+
+        for (column, problem) in cells.iter_mut().zip(&problems) {
+            column.push(String::new());
+            let mut lines: Vec<String> = match problem {
+                Ok(p) => p.operands.iter().map(|n| n.to_string()).collect(),
+                Err(e) => vec![format!("error: {}", e.message)],
+            };
+            lines.resize(operand_lines, String::new());
+            column.extend(lines);
+            column.push(match problem {
+                Ok(p) => match p.solve() {
+                    Ok(result) => format!("{} = {}", p.operator, result),
+                    Err(e) => format!("{} = error: {}", p.operator, e.message),
+                },
+                Err(_) => String::new(),
+            });
+        }
+    }
+
+    let label_width = labels.iter().map(|l| l.len()).max().unwrap_or(0);
+    let widths: Vec<usize> = cells
+        .iter()
+        .map(|column| column.iter().map(|c| c.len()).max().unwrap_or(0))
+        .collect();
+
+    let mut out = String::new();
+    for (i, label) in labels.iter().enumerate() {
+        let mut line = format!("{:<width$}", label, width = label_width);
+        for (column, &width) in cells.iter().zip(&widths) {
+            line.push_str(&format!(" | {:<width$}", column[i], width = width));
+        }
+        out.push_str(line.trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+fn read_grid(path: &str) -> io::Result<Grid<char>> {
+    let content = fs::read_to_string(path)?;
+    let mut lines: Vec<&str> = content.lines().collect();
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+    Ok(Grid::from_lines(lines, ' '))
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let explain_blocks = args.iter().any(|a| a == "--explain");
+    // The explanation is laid out from the grid, so it takes precedence.
+    let from_bytes = args.iter().any(|a| a == "--bytes") && !explain_blocks;
+    let input_path = args
+        .iter()
+        .skip(1)
+        .find(|a| !a.starts_with("--"))
+        .map_or(DEFAULT_INPUT, |a| a.as_str());
+    println!("Reading from: {}", input_path);
+
+    let totals = if from_bytes {
+        match fs::read(input_path) {
+            Ok(content) => solve_bytes(&content),
+            Err(e) => {
+                eprintln!("Error reading file: {}", e);
+                return;
+            }
+        }
+    } else {
+        let grid = match read_grid(input_path) {
+            Ok(g) => g,
+            Err(e) => {
+                eprintln!("Error reading file: {}", e);
+                return;
+            }
+        };
+        if grid.height() == 0 {
+            return;
+        }
+
+        if explain_blocks {
+            print!("{}", explain(&grid));
+        }
+
+        solve(&grid, Reading::RowWise).and_then(|p1| Ok((p1, solve(&grid, Reading::ColumnWise)?)))
+    };
+
+    let (total_p1, total_p2) = match totals {
+        Ok(totals) => totals,
+        Err(e) => {
+            eprintln!("Error solving worksheet: {}", e);
+            return;
+        }
+    };
 
     println!("Part 1: {}", total_p1);
     println!("Part 2: {}", total_p2);
@@ -1,148 +1,1094 @@
+mod grid;
+
+use grid::Grid;
+use std::collections::HashMap;
 use std::env;
+use std::fmt;
 use std::fs;
-use std::io::{self, BufRead};
 use std::path::Path;
 
-fn read_lines<P>(filename: P) -> io::Result<Vec<String>>
-where
-    P: AsRef<Path>,
-{
-    let file = fs::File::open(filename)?;
-    let buf = io::BufReader::new(file);
-    buf.lines().collect()
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Dir {
+    Up,
+    Down,
+    Left,
+    Right,
 }
 
-fn solve() {
-    let args: Vec<String> = env::args().collect();
-    let default_input = "../input/day07.txt";
-    let input_path = if args.len() > 1 {
-        &args[1]
-    } else {
-        default_input
-    };
+impl Dir {
+    const ALL: [Dir; 4] = [Dir::Up, Dir::Down, Dir::Left, Dir::Right];
 
-    println!("Reading from: {}", input_path);
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "up" => Some(Dir::Up),
+            "down" => Some(Dir::Down),
+            "left" => Some(Dir::Left),
+            "right" => Some(Dir::Right),
+            _ => None,
+        }
+    }
 
-    let lines = match read_lines(input_path) {
-        Ok(l) => l,
-        Err(_) => {
-            eprintln!("Error reading file: {}", input_path);
-            return;
+    fn delta(self) -> (isize, isize) {
+        match self {
+            Dir::Up => (-1, 0),
+            Dir::Down => (1, 0),
+            Dir::Left => (0, -1),
+            Dir::Right => (0, 1),
         }
-    };
+    }
 
-    let grid: Vec<Vec<char>> = lines
-        .iter()
-        .filter(|l| !l.is_empty())
-        .map(|l| l.chars().collect())
-        .collect();
+    fn index(self) -> usize {
+        match self {
+            Dir::Up => 0,
+            Dir::Down => 1,
+            Dir::Left => 2,
+            Dir::Right => 3,
+        }
+    }
 
-    if grid.is_empty() {
-        println!("Grid is empty");
-        return;
+    fn turn(self, turn: Turn) -> Dir {
+        match (turn, self) {
+            (Turn::Straight, d) => d,
+            (Turn::Back, Dir::Up) | (Turn::Left, Dir::Right) | (Turn::Right, Dir::Left) => {
+                Dir::Down
+            }
+            (Turn::Back, Dir::Down) | (Turn::Left, Dir::Left) | (Turn::Right, Dir::Right) => {
+                Dir::Up
+            }
+            (Turn::Back, Dir::Left) | (Turn::Left, Dir::Down) | (Turn::Right, Dir::Up) => {
+                Dir::Right
+            }
+            (Turn::Back, Dir::Right) | (Turn::Left, Dir::Up) | (Turn::Right, Dir::Down) => {
+                Dir::Left
+            }
+        }
+    }
+}
+
+/// A direction relative to the beam's current heading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Turn {
+    Left,
+    Straight,
+    Right,
+    Back,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mirror {
+    /// `/`: reflects a beam heading right to up.
+    Slash,
+    /// `\`: reflects a beam heading right to down.
+    Backslash,
+}
+
+impl Mirror {
+    fn reflect(self, dir: Dir) -> Dir {
+        match (self, dir) {
+            (Mirror::Slash, Dir::Right) | (Mirror::Backslash, Dir::Left) => Dir::Up,
+            (Mirror::Slash, Dir::Left) | (Mirror::Backslash, Dir::Right) => Dir::Down,
+            (Mirror::Slash, Dir::Up) | (Mirror::Backslash, Dir::Down) => Dir::Right,
+            (Mirror::Slash, Dir::Down) | (Mirror::Backslash, Dir::Up) => Dir::Left,
+        }
     }
+}
 
-    let height = grid.len();
-    let width = grid[0].len();
-    let mut start_pos: Option<(usize, usize)> = None;
+/// What a cell does to a beam that reaches it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cell {
+    /// Lets the beam carry on.
+    Empty,
+    /// The puzzle's `^`: the beam stops and new beams leave from the cells on
+    /// either side, heading the same way. Beams that would start outside the
+    /// manifold are lost.
+    Splitter,
+    /// Sends a beam out of this cell in each of the given relative directions.
+    Fan(Vec<Turn>),
+    /// Reflects the beam through 90 degrees.
+    Mirror(Mirror),
+    /// Stops the beam; its timeline ends without leaving the manifold.
+    Absorber,
+    /// Turns every beam to the given direction, except beams arriving head-on,
+    /// which are stopped.
+    Deflector(Dir),
+}
 
-    for r in 0..height {
-        for c in 0..width {
-            if grid[r][c] == 'S' {
-                start_pos = Some((r, c));
-                break;
+impl Cell {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "empty" => Some(Cell::Empty),
+            "split" => Some(Cell::Splitter),
+            "mirror-slash" => Some(Cell::Mirror(Mirror::Slash)),
+            "mirror-backslash" => Some(Cell::Mirror(Mirror::Backslash)),
+            "absorb" => Some(Cell::Absorber),
+            _ => {
+                if let Some(dir) = s.strip_prefix("deflect-") {
+                    return Dir::parse(dir).map(Cell::Deflector);
+                }
+                let turns = s.strip_prefix("fan:")?;
+                let turns = turns
+                    .chars()
+                    .map(|c| match c {
+                        'L' => Some(Turn::Left),
+                        'S' => Some(Turn::Straight),
+                        'R' => Some(Turn::Right),
+                        'B' => Some(Turn::Back),
+                        _ => None,
+                    })
+                    .collect::<Option<Vec<Turn>>>()?;
+                Some(Cell::Fan(turns))
             }
         }
-        if start_pos.is_some() {
-            break;
+    }
+
+    fn is_splitter(&self) -> bool {
+        matches!(self, Cell::Splitter | Cell::Fan(_))
+    }
+}
+
+pub fn default_behaviours() -> HashMap<char, Cell> {
+    HashMap::from([
+        ('.', Cell::Empty),
+        ('S', Cell::Empty),
+        ('^', Cell::Splitter),
+        ('/', Cell::Mirror(Mirror::Slash)),
+        ('\\', Cell::Mirror(Mirror::Backslash)),
+        ('#', Cell::Absorber),
+        ('>', Cell::Deflector(Dir::Right)),
+        ('<', Cell::Deflector(Dir::Left)),
+        ('v', Cell::Deflector(Dir::Down)),
+        (
+            '+',
+            Cell::Fan(vec![Turn::Left, Turn::Straight, Turn::Right]),
+        ),
+    ])
+}
+
+/// A number of timelines. Additions report overflow instead of wrapping, so a
+/// count that no longer fits is an error rather than a silently wrong answer.
+pub trait TimelineCount: Clone + fmt::Display {
+    /// The additive identity in the same representation as `self` (same modulus, say).
+    fn zero_like(&self) -> Self;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+}
+
+impl TimelineCount for u64 {
+    fn zero_like(&self) -> Self {
+        0
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        u64::checked_add(*self, *other)
+    }
+}
+
+impl TimelineCount for u128 {
+    fn zero_like(&self) -> Self {
+        0
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        u128::checked_add(*self, *other)
+    }
+}
+
+/// Unbounded unsigned integer stored as little-endian base-10^9 limbs,
+/// which keeps addition simple and makes printing a matter of padding limbs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    const BASE: u32 = 1_000_000_000;
+
+    pub fn one() -> Self {
+        BigUint { limbs: vec![1] }
+    }
+}
+
+impl TimelineCount for BigUint {
+    fn zero_like(&self) -> Self {
+        BigUint { limbs: Vec::new() }
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        let len = self.limbs.len().max(other.limbs.len());
+        let mut limbs = Vec::with_capacity(len + 1);
+        let mut carry = 0;
+        for i in 0..len {
+            let a = self.limbs.get(i).copied().unwrap_or(0);
+            let b = other.limbs.get(i).copied().unwrap_or(0);
+            let sum = a + b + carry;
+            limbs.push(sum % BigUint::BASE);
+            carry = sum / BigUint::BASE;
+        }
+        if carry > 0 {
+            limbs.push(carry);
         }
+        Some(BigUint { limbs })
     }
+}
 
-    let (start_r, start_c) = match start_pos {
-        Some(pos) => pos,
-        None => {
-            eprintln!("Could not find start point 'S'");
-            return;
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.limbs.split_last() {
+            None => write!(f, "0"),
+            Some((top, rest)) => {
+                write!(f, "{}", top)?;
+                for limb in rest.iter().rev() {
+                    write!(f, "{:09}", limb)?;
+                }
+                Ok(())
+            }
         }
-    };
+    }
+}
 
-    let mut current_beams: Vec<usize> = vec![start_c];
-    let mut total_splits = 0;
+/// A count reduced modulo `modulus`; it never overflows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Modular {
+    pub value: u64,
+    pub modulus: u64,
+}
 
-    for r in start_r..height {
-        if current_beams.is_empty() {
-            break;
+impl TimelineCount for Modular {
+    fn zero_like(&self) -> Self {
+        Modular {
+            value: 0,
+            modulus: self.modulus,
         }
+    }
 
-        let mut next_beams: Vec<usize> = Vec::new();
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        let value = (self.value as u128 + other.value as u128) % self.modulus as u128;
+        Some(Modular {
+            value: value as u64,
+            modulus: self.modulus,
+        })
+    }
+}
 
-        for &c in &current_beams {
-            if c >= width {
-                continue;
+impl fmt::Display for Modular {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+/// Which representation `--count` selects for timeline counts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CountKind {
+    U64,
+    U128,
+    BigInt,
+    Mod(u64),
+}
+
+impl CountKind {
+    const DEFAULT_MODULUS: u64 = 1_000_000_007;
+
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "u64" => Some(CountKind::U64),
+            "u128" => Some(CountKind::U128),
+            "bigint" => Some(CountKind::BigInt),
+            "mod" => Some(CountKind::Mod(CountKind::DEFAULT_MODULUS)),
+            _ => {
+                let modulus = s.strip_prefix("mod:")?.parse::<u64>().ok()?;
+                (modulus > 0).then_some(CountKind::Mod(modulus))
             }
+        }
+    }
+}
 
-            let cell = grid[r][c];
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ManifoldError {
+    Empty,
+    MissingStart,
+    /// A beam can come back to a state it has already passed through.
+    Loop {
+        row: usize,
+        col: usize,
+    },
+    Overflow {
+        count_type: &'static str,
+        row: usize,
+        col: usize,
+    },
+}
 
-            if cell == '^' {
-                total_splits += 1;
-                if c > 0 {
-                    next_beams.push(c - 1);
-                }
-                if c + 1 < width {
-                    next_beams.push(c + 1);
+impl fmt::Display for ManifoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ManifoldError::Empty => write!(f, "Grid is empty"),
+            ManifoldError::MissingStart => write!(f, "Could not find start point 'S'"),
+            ManifoldError::Loop { row, col } => write!(
+                f,
+                "Beam loops back to row {}, column {}; timelines are unbounded",
+                row, col
+            ),
+            ManifoldError::Overflow {
+                count_type,
+                row,
+                col,
+            } => write!(
+                f,
+                "Timeline count overflows {} at row {}, column {}; \
+                 choose a wider representation with --count",
+                count_type, row, col
+            ),
+        }
+    }
+}
+
+/// Where a beam goes after interacting with a cell.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Next {
+    Beam(usize, usize, Dir),
+    Exit,
+}
+
+pub struct Manifold {
+    cells: Grid<Cell>,
+    start: (usize, usize),
+    dir: Dir,
+}
+
+impl Manifold {
+    fn offset(&self, r: usize, c: usize, dir: Dir) -> Option<(usize, usize)> {
+        let (dr, dc) = dir.delta();
+        let (nr, nc) = (r as isize + dr, c as isize + dc);
+        self.cells.get(nr as usize, nc as usize)?;
+        Some((nr as usize, nc as usize))
+    }
+
+    /// Steps a beam leaving `(r, c)` heading `dir` into its next cell.
+    fn advance(&self, r: usize, c: usize, dir: Dir) -> Next {
+        match self.offset(r, c, dir) {
+            Some((nr, nc)) => Next::Beam(nr, nc, dir),
+            None => Next::Exit,
+        }
+    }
+
+    /// The beams produced when a beam heading `dir` reaches `(r, c)`, each
+    /// labelled with the choice it represents: a splitter's side as seen on the
+    /// printed grid (`L`/`R` for west/east of a vertical beam, `U`/`D` for
+    /// north/south of a horizontal one), the turn letter for a fan, and `-`
+    /// where the beam has no choice.
+    fn branches(&self, r: usize, c: usize, dir: Dir) -> Vec<(char, Next)> {
+        match &self.cells[(r, c)] {
+            Cell::Empty => vec![('-', self.advance(r, c, dir))],
+            Cell::Splitter => match dir {
+                Dir::Up | Dir::Down => [('L', Dir::Left), ('R', Dir::Right)],
+                Dir::Left | Dir::Right => [('U', Dir::Up), ('D', Dir::Down)],
+            }
+            .iter()
+            .filter_map(|&(label, side)| {
+                let (sr, sc) = self.offset(r, c, side)?;
+                Some((label, self.advance(sr, sc, dir)))
+            })
+            .collect(),
+            Cell::Fan(turns) => turns
+                .iter()
+                .map(|&turn| {
+                    let label = match turn {
+                        Turn::Left => 'L',
+                        Turn::Straight => 'S',
+                        Turn::Right => 'R',
+                        Turn::Back => 'B',
+                    };
+                    (label, self.advance(r, c, dir.turn(turn)))
+                })
+                .collect(),
+            Cell::Mirror(mirror) => vec![('-', self.advance(r, c, mirror.reflect(dir)))],
+            Cell::Absorber => Vec::new(),
+            Cell::Deflector(out) if out.turn(Turn::Back) == dir => Vec::new(),
+            Cell::Deflector(out) => vec![('-', self.advance(r, c, *out))],
+        }
+    }
+
+    fn successors(&self, r: usize, c: usize, dir: Dir) -> Vec<Next> {
+        self.branches(r, c, dir)
+            .into_iter()
+            .map(|(_, next)| next)
+            .collect()
+    }
+
+    fn state_count(&self) -> usize {
+        self.cells.width() * self.cells.height() * Dir::ALL.len()
+    }
+
+    fn state_index(&self, r: usize, c: usize, dir: Dir) -> usize {
+        (r * self.cells.width() + c) * Dir::ALL.len() + dir.index()
+    }
+}
+
+/// Builds a manifold from its text, one row per non-empty line, with short
+/// rows padded with empty cells. Characters without a behaviour are empty.
+pub fn parse_manifold(
+    text: &str,
+    dir: Dir,
+    behaviours: &HashMap<char, Cell>,
+) -> Result<Manifold, ManifoldError> {
+    let grid = Grid::from_lines(text.lines().filter(|l| !l.is_empty()), '.');
+    if grid.is_empty() {
+        return Err(ManifoldError::Empty);
+    }
+
+    let start = grid
+        .find(|&cell| cell == 'S')
+        .ok_or(ManifoldError::MissingStart)?;
+    let rows: Vec<Vec<Cell>> = grid
+        .rows()
+        .map(|row| {
+            row.iter()
+                .map(|ch| behaviours.get(ch).cloned().unwrap_or(Cell::Empty))
+                .collect()
+        })
+        .collect();
+
+    Ok(Manifold {
+        cells: Grid::from_rows(rows, Cell::Empty),
+        start,
+        dir,
+    })
+}
+
+/// Number of distinct splitter cells reached by any beam.
+pub fn count_splits(manifold: &Manifold) -> usize {
+    let mut seen = vec![false; manifold.state_count()];
+    let mut split = Grid::new(manifold.cells.width(), manifold.cells.height(), false);
+    let mut stack = vec![(manifold.start.0, manifold.start.1, manifold.dir)];
+
+    while let Some((r, c, dir)) = stack.pop() {
+        let idx = manifold.state_index(r, c, dir);
+        if seen[idx] {
+            continue;
+        }
+        seen[idx] = true;
+        if manifold.cells[(r, c)].is_splitter() {
+            split[(r, c)] = true;
+        }
+        for next in manifold.successors(r, c, dir) {
+            if let Next::Beam(nr, nc, nd) = next {
+                stack.push((nr, nc, nd));
+            }
+        }
+    }
+
+    split.positions().filter(|&pos| split[pos]).count()
+}
+
+/// Number of distinct paths from the start that leave the manifold, counted
+/// in the representation of `one`. Fails if a beam can loop back onto its own
+/// path, since the count would then be unbounded, or if the count overflows.
+pub fn count_timelines<T: TimelineCount>(manifold: &Manifold, one: T) -> Result<T, ManifoldError> {
+    let (mut counts, _) = timelines_per_state(manifold, one)?;
+    let start = manifold.state_index(manifold.start.0, manifold.start.1, manifold.dir);
+    Ok(counts.swap_remove(start))
+}
+
+/// For every beam state, the number of timelines leading from it out of the
+/// manifold, plus the reachable states in post-order (every state after all of
+/// its successors).
+fn timelines_per_state<T: TimelineCount>(
+    manifold: &Manifold,
+    one: T,
+) -> Result<(Vec<T>, Vec<usize>), ManifoldError> {
+    const UNVISITED: u8 = 0;
+    const ON_PATH: u8 = 1;
+    const DONE: u8 = 2;
+
+    let mut state = vec![UNVISITED; manifold.state_count()];
+    let mut counts = vec![one.zero_like(); manifold.state_count()];
+    let mut post_order = Vec::new();
+    // Iterative post-order DFS so tall manifolds don't exhaust the call stack.
+    let mut stack = vec![(manifold.start.0, manifold.start.1, manifold.dir, false)];
+
+    while let Some((r, c, dir, expanded)) = stack.pop() {
+        let idx = manifold.state_index(r, c, dir);
+        let successors = manifold.successors(r, c, dir);
+
+        if expanded {
+            let mut total = one.zero_like();
+            for next in &successors {
+                let count = match *next {
+                    Next::Exit => &one,
+                    Next::Beam(nr, nc, nd) => &counts[manifold.state_index(nr, nc, nd)],
+                };
+                total = total.checked_add(count).ok_or(ManifoldError::Overflow {
+                    count_type: std::any::type_name::<T>(),
+                    row: r,
+                    col: c,
+                })?;
+            }
+            counts[idx] = total;
+            state[idx] = DONE;
+            post_order.push(idx);
+            continue;
+        }
+        if state[idx] == DONE {
+            continue;
+        }
+
+        state[idx] = ON_PATH;
+        stack.push((r, c, dir, true));
+        for next in successors {
+            if let Next::Beam(nr, nc, nd) = next {
+                match state[manifold.state_index(nr, nc, nd)] {
+                    ON_PATH => return Err(ManifoldError::Loop { row: nr, col: nc }),
+                    UNVISITED => stack.push((nr, nc, nd, false)),
+                    _ => {}
                 }
-            } else {
-                next_beams.push(c);
             }
         }
+    }
+
+    Ok((counts, post_order))
+}
+
+/// Timeline counts in both directions for every beam state, in `u128`.
+pub struct Flow {
+    /// Timelines from the state out of the manifold.
+    to_exit: Vec<u128>,
+    /// Ways of reaching the state from the start.
+    from_start: Vec<u128>,
+}
+
+impl Flow {
+    fn through_state(&self, idx: usize) -> Option<u128> {
+        self.from_start[idx].checked_mul(self.to_exit[idx])
+    }
+
+    /// Beam visits to `(r, c)` summed over all timelines and directions. A
+    /// timeline that crosses the cell in two directions counts twice, so this
+    /// is an upper bound on the distinct timelines through the cell.
+    fn through_cell(&self, manifold: &Manifold, r: usize, c: usize) -> Result<u128, ManifoldError> {
+        let overflow = ManifoldError::Overflow {
+            count_type: "u128",
+            row: r,
+            col: c,
+        };
+        Dir::ALL.iter().try_fold(0u128, |acc, &dir| {
+            let through = self.through_state(manifold.state_index(r, c, dir));
+            through
+                .and_then(|t| acc.checked_add(t))
+                .ok_or(overflow.clone())
+        })
+    }
+}
+
+pub fn timeline_flow(manifold: &Manifold) -> Result<Flow, ManifoldError> {
+    let (to_exit, post_order) = timelines_per_state(manifold, 1u128)?;
+    let mut from_start = vec![0u128; manifold.state_count()];
+    from_start[manifold.state_index(manifold.start.0, manifold.start.1, manifold.dir)] = 1;
 
-        next_beams.sort_unstable();
-        next_beams.dedup();
-        current_beams = next_beams;
+    let width = manifold.cells.width();
+    for &idx in post_order.iter().rev() {
+        let (cell, dir) = (idx / Dir::ALL.len(), Dir::ALL[idx % Dir::ALL.len()]);
+        let (r, c) = (cell / width, cell % width);
+        for next in manifold.successors(r, c, dir) {
+            if let Next::Beam(nr, nc, nd) = next {
+                let succ = manifold.state_index(nr, nc, nd);
+                from_start[succ] = from_start[succ].checked_add(from_start[idx]).ok_or(
+                    ManifoldError::Overflow {
+                        count_type: "u128",
+                        row: nr,
+                        col: nc,
+                    },
+                )?;
+            }
+        }
     }
 
-    println!("Part 1: {}", total_splits);
+    Ok(Flow {
+        to_exit,
+        from_start,
+    })
+}
+
+pub struct SplitterStats {
+    /// `(row, col, visits)` for every splitter at least one beam reaches, with
+    /// visits counted as in [`Flow::through_cell`].
+    pub reached: Vec<(usize, usize, u128)>,
+    pub unreached: Vec<(usize, usize)>,
+}
 
-    let mut counts: Vec<u64> = vec![0; width];
-    counts[start_c] = 1;
+pub fn splitter_stats(manifold: &Manifold, flow: &Flow) -> Result<SplitterStats, ManifoldError> {
+    let mut stats = SplitterStats {
+        reached: Vec::new(),
+        unreached: Vec::new(),
+    };
+    for (r, c) in manifold.cells.positions() {
+        if !manifold.cells[(r, c)].is_splitter() {
+            continue;
+        }
+        let reached = Dir::ALL
+            .iter()
+            .any(|&dir| flow.from_start[manifold.state_index(r, c, dir)] > 0);
+        if reached {
+            stats
+                .reached
+                .push((r, c, flow.through_cell(manifold, r, c)?));
+        } else {
+            stats.unreached.push((r, c));
+        }
+    }
+    Ok(stats)
+}
 
-    for r in start_r..height {
-        let mut next_counts: Vec<u64> = vec![0; width];
+/// Lists up to `limit` timelines as the choices made at each branching cell,
+/// in depth-first order.
+pub fn enumerate_timelines(manifold: &Manifold, flow: &Flow, limit: usize) -> Vec<String> {
+    let mut timelines = Vec::new();
+    let start = Next::Beam(manifold.start.0, manifold.start.1, manifold.dir);
+    let mut stack = vec![(start, String::new())];
 
-        for c in 0..width {
-            if counts[c] == 0 {
+    while let Some((next, path)) = stack.pop() {
+        if timelines.len() >= limit {
+            break;
+        }
+        let (r, c, dir) = match next {
+            Next::Exit => {
+                timelines.push(path);
                 continue;
             }
+            Next::Beam(r, c, dir) => (r, c, dir),
+        };
+        let branches = manifold.branches(r, c, dir);
+        let choosing = branches.len() > 1;
+        for (label, next) in branches.into_iter().rev() {
+            if let Next::Beam(nr, nc, nd) = next {
+                if flow.to_exit[manifold.state_index(nr, nc, nd)] == 0 {
+                    continue;
+                }
+            }
+            let mut path = path.clone();
+            if choosing {
+                path.push(label);
+            }
+            stack.push((next, path));
+        }
+    }
+    timelines
+}
+
+/// Small xorshift generator so sampling is reproducible from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, bound: u128) -> u128 {
+        let wide = ((self.next_u64() as u128) << 64) | self.next_u64() as u128;
+        wide % bound
+    }
+}
 
-            let cell = grid[r][c];
-            let count = counts[c];
+/// Draws `samples` timelines uniformly at random: at each branch a beam follows
+/// a successor with probability proportional to the timelines through it.
+pub fn sample_timelines(
+    manifold: &Manifold,
+    flow: &Flow,
+    samples: usize,
+    seed: u64,
+) -> Vec<String> {
+    let start = manifold.state_index(manifold.start.0, manifold.start.1, manifold.dir);
+    if flow.to_exit[start] == 0 {
+        return Vec::new();
+    }
+
+    let mut rng = Rng::new(seed);
+    let weight = |next: &Next| match *next {
+        Next::Exit => 1,
+        Next::Beam(nr, nc, nd) => flow.to_exit[manifold.state_index(nr, nc, nd)],
+    };
 
-            if cell == '^' {
-                if c > 0 {
-                    next_counts[c - 1] += count;
+    (0..samples)
+        .map(|_| {
+            let mut path = String::new();
+            let (mut r, mut c, mut dir) = (manifold.start.0, manifold.start.1, manifold.dir);
+            loop {
+                let branches = manifold.branches(r, c, dir);
+                let total: u128 = branches.iter().map(|(_, next)| weight(next)).sum();
+                let mut pick = rng.below(total);
+                let (label, next) = branches
+                    .iter()
+                    .find(|(_, next)| {
+                        let w = weight(next);
+                        if pick < w {
+                            true
+                        } else {
+                            pick -= w;
+                            false
+                        }
+                    })
+                    .copied()
+                    .expect("weights sum to total");
+                if branches.len() > 1 {
+                    path.push(label);
                 }
-                if c + 1 < width {
-                    next_counts[c + 1] += count;
+                match next {
+                    Next::Exit => break path,
+                    Next::Beam(nr, nc, nd) => (r, c, dir) = (nr, nc, nd),
                 }
+            }
+        })
+        .collect()
+}
+
+/// Renders how many beam visits each cell gets across all timelines (see
+/// [`Flow::through_cell`]) on a logarithmic ramp, from blank (none) to `@`
+/// (the busiest cell).
+pub fn render_heatmap(manifold: &Manifold, flow: &Flow) -> Result<String, ManifoldError> {
+    const RAMP: &[u8] = b" .:-=+*#%@";
+
+    let mut density = Grid::new(manifold.cells.width(), manifold.cells.height(), 0u128);
+    for (r, c) in manifold.cells.positions() {
+        density[(r, c)] = flow.through_cell(manifold, r, c)?;
+    }
+    let max = density
+        .positions()
+        .map(|pos| density[pos])
+        .max()
+        .unwrap_or(0);
+    let log = |n: u128| (n as f64 + 1.0).ln();
+
+    let mut out = String::new();
+    for r in 0..density.height() {
+        for c in 0..density.width() {
+            let d = density[(r, c)];
+            let ch = if d == 0 {
+                ' '
             } else {
-                next_counts[c] += count;
+                let level = 1 + ((log(d) / log(max)) * (RAMP.len() - 2) as f64).round() as usize;
+                RAMP[level.min(RAMP.len() - 1)] as char
+            };
+            out.push(ch);
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+struct Options {
+    input_path: String,
+    dir: Dir,
+    behaviours: HashMap<char, Cell>,
+    count_kind: CountKind,
+    stats: bool,
+    paths: Option<usize>,
+    sample: Option<usize>,
+    seed: u64,
+    heatmap: bool,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        input_path: "../input/day07.txt".to_string(),
+        dir: Dir::Down,
+        behaviours: default_behaviours(),
+        count_kind: CountKind::U64,
+        stats: false,
+        paths: None,
+        sample: None,
+        seed: 1,
+        heatmap: false,
+    };
+
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--dir" => {
+                options.dir = iter
+                    .next()
+                    .and_then(|d| Dir::parse(d))
+                    .ok_or("--dir expects one of up, down, left, right")?;
+            }
+            "--count" => {
+                options.count_kind = iter
+                    .next()
+                    .and_then(|k| CountKind::parse(k))
+                    .ok_or("--count expects one of u64, u128, bigint, mod, mod:<modulus>")?;
+            }
+            "--stats" => options.stats = true,
+            "--heatmap" => options.heatmap = true,
+            "--paths" => {
+                options.paths = Some(
+                    iter.next()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or("--paths expects a number of timelines")?,
+                );
             }
+            "--sample" => {
+                options.sample = Some(
+                    iter.next()
+                        .and_then(|n| n.parse().ok())
+                        .ok_or("--sample expects a number of timelines")?,
+                );
+            }
+            "--seed" => {
+                options.seed = iter
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or("--seed expects an integer")?;
+            }
+            "--cell" => {
+                let spec = iter.next().map(|s| s.as_str()).unwrap_or("");
+                let (ch, cell) = spec
+                    .split_once('=')
+                    .and_then(|(ch, kind)| {
+                        let mut chars = ch.chars();
+                        match (chars.next(), chars.next(), Cell::parse(kind)) {
+                            (Some(ch), None, Some(cell)) => Some((ch, cell)),
+                            _ => None,
+                        }
+                    })
+                    .ok_or_else(|| {
+                        format!("Invalid --cell spec '{}', expected e.g. 'x=absorb'", spec)
+                    })?;
+                options.behaviours.insert(ch, cell);
+            }
+            _ => options.input_path = arg.clone(),
         }
+    }
+    Ok(options)
+}
 
-        counts = next_counts;
+/// Counts timelines in the representation chosen by `kind`, formatted for output.
+fn count_timelines_as(manifold: &Manifold, kind: CountKind) -> Result<String, ManifoldError> {
+    match kind {
+        CountKind::U64 => count_timelines(manifold, 1u64).map(|t| t.to_string()),
+        CountKind::U128 => count_timelines(manifold, 1u128).map(|t| t.to_string()),
+        CountKind::BigInt => count_timelines(manifold, BigUint::one()).map(|t| t.to_string()),
+        CountKind::Mod(modulus) => count_timelines(
+            manifold,
+            Modular {
+                value: 1 % modulus,
+                modulus,
+            },
+        )
+        .map(|t| t.to_string()),
     }
+}
 
-    let total_timelines: u64 = counts.iter().sum();
-    println!("Part 2: {}", total_timelines);
+/// Prints the optional per-splitter statistics, timelines and heatmap.
+fn explore(manifold: &Manifold, options: &Options) -> Result<(), ManifoldError> {
+    let flow = timeline_flow(manifold)?;
+
+    if options.stats {
+        let stats = splitter_stats(manifold, &flow)?;
+        println!("Splitters reached: {}", stats.reached.len());
+        for (r, c, visits) in &stats.reached {
+            println!("  ({}, {}): {} visits", r, c, visits);
+        }
+        println!("Splitters never reached: {}", stats.unreached.len());
+        for (r, c) in &stats.unreached {
+            println!("  ({}, {})", r, c);
+        }
+    }
+    if let Some(limit) = options.paths {
+        println!("First {} timelines:", limit);
+        for path in enumerate_timelines(manifold, &flow, limit) {
+            println!("  {}", path);
+        }
+    }
+    if let Some(samples) = options.sample {
+        println!("Sampled timelines (seed {}):", options.seed);
+        for path in sample_timelines(manifold, &flow, samples, options.seed) {
+            println!("  {}", path);
+        }
+    }
+    if options.heatmap {
+        print!("{}", render_heatmap(manifold, &flow)?);
+    }
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let options = match parse_args(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
+
+    println!("Reading from: {}", options.input_path);
+
+    let text = match fs::read_to_string(&options.input_path) {
+        Ok(t) => t,
+        Err(_) => {
+            eprintln!("Error reading file: {}", options.input_path);
+            return;
+        }
+    };
+
+    let results = parse_manifold(&text, options.dir, &options.behaviours).and_then(|manifold| {
+        let splits = count_splits(&manifold);
+        println!("Part 1: {}", splits);
+        let timelines = count_timelines_as(&manifold, options.count_kind)?;
+        println!("Part 2: {}", timelines);
+        if options.stats || options.paths.is_some() || options.sample.is_some() || options.heatmap {
+            // Statistics are optional, so a failure here shouldn't lose the answers.
+            if let Err(e) = explore(&manifold, &options) {
+                eprintln!("{}", e);
+            }
+        }
+        Ok((splits, timelines))
+    });
+    let (total_splits, total_timelines) = match results {
+        Ok(r) => r,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    };
 
     let output_path = "output/day07.txt";
     if let Some(parent) = Path::new(output_path).parent() {
         let _ = fs::create_dir_all(parent);
     }
     let output_content = format!("{}\n{}\n", total_splits, total_timelines);
-    if let Err(_) = fs::write(output_path, output_content) {}
+    if let Err(e) = fs::write(output_path, output_content) {
+        eprintln!("Error writing output: {}", e);
+    }
 }
 
-fn main() {
-    solve();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+.......S.......
+...............
+.......^.......
+...............
+......^.^......
+...............
+.....^.^.^.....
+...............
+....^.^...^....
+...............
+...^.^...^.^...
+...............
+..^...^.....^..
+...............
+.^.^.^.^.^...^.
+...............";
+
+    fn parse(text: &str) -> Result<Manifold, ManifoldError> {
+        parse_manifold(text, Dir::Down, &default_behaviours())
+    }
+
+    fn counts(text: &str) -> (usize, u64) {
+        let manifold = parse(text).unwrap();
+        (
+            count_splits(&manifold),
+            count_timelines(&manifold, 1u64).unwrap(),
+        )
+    }
+
+    #[test]
+    fn sample_counts() {
+        assert_eq!(counts(SAMPLE), (21, 40));
+    }
+
+    #[test]
+    fn missing_start_is_an_error() {
+        assert!(matches!(
+            parse("...\n.^.\n..."),
+            Err(ManifoldError::MissingStart)
+        ));
+    }
+
+    #[test]
+    fn empty_input_is_an_error() {
+        assert!(matches!(parse(""), Err(ManifoldError::Empty)));
+        assert!(matches!(parse("\n\n"), Err(ManifoldError::Empty)));
+    }
+
+    #[test]
+    fn beam_leaving_the_bottom_is_one_timeline() {
+        assert_eq!(counts("S"), (0, 1));
+        assert_eq!(counts(".S.\n...\n..."), (0, 1));
+    }
+
+    #[test]
+    fn splitter_on_an_edge_loses_the_outer_beam() {
+        // The right-hand beam of each splitter would leave the grid sideways.
+        assert_eq!(counts("S\n^"), (1, 0));
+        assert_eq!(counts(".S\n.^\n.."), (1, 1));
+        assert_eq!(counts("S.\n^.\n.."), (1, 1));
+    }
+
+    #[test]
+    fn ragged_rows_are_padded_with_empty_cells() {
+        let ragged = "..S\n\n..^\n.\n..";
+        let padded = "..S\n..^\n...\n...";
+        assert_eq!(counts(ragged), counts(padded));
+        assert_eq!(counts(ragged), (1, 1));
+
+        // The beam misses the splitter on the second row but hits the one on
+        // the short third row, whose padding lets both halves continue.
+        assert_eq!(counts(".S..\n..^\n.^\n"), (1, 2));
+    }
+
+    fn paths_and_samples(text: &str, dir: Dir) -> (Vec<String>, Vec<String>) {
+        let manifold = parse_manifold(text, dir, &default_behaviours()).unwrap();
+        let flow = timeline_flow(&manifold).unwrap();
+        let mut samples = sample_timelines(&manifold, &flow, 5, 1);
+        samples.sort();
+        samples.dedup();
+        (enumerate_timelines(&manifold, &flow, 10), samples)
+    }
+
+    #[test]
+    fn splitter_labels_follow_the_printed_grid() {
+        // The absorber below-left of the splitter kills the west beam, so the
+        // only timeline takes the east side, column 2.
+        let east = vec!["R".to_string()];
+        assert_eq!(
+            paths_and_samples(".S.\n.^.\n#..\n...", Dir::Down),
+            (east.clone(), east.clone())
+        );
+        assert_eq!(
+            paths_and_samples("#..\n.^.\n.S.", Dir::Up),
+            (east.clone(), east)
+        );
+
+        // Heading right, the absorber above-right kills the north beam.
+        let south = vec!["D".to_string()];
+        assert_eq!(
+            paths_and_samples("..#.\nS^..\n....", Dir::Right),
+            (south.clone(), south)
+        );
+
+        // With both sides open, west comes before east.
+        assert_eq!(
+            paths_and_samples(".S.\n.^.\n...", Dir::Down).0,
+            vec!["L".to_string(), "R".to_string()]
+        );
+    }
+
+    #[test]
+    fn through_cell_counts_each_crossing() {
+        // One timeline runs along the bottom row, loops up and round, and
+        // comes back down through (3, 1).
+        let manifold = parse("S..\n./\\\n...\n\\./").unwrap();
+        let flow = timeline_flow(&manifold).unwrap();
+        assert_eq!(count_timelines(&manifold, 1u64).unwrap(), 1);
+        assert_eq!(flow.through_cell(&manifold, 3, 1).unwrap(), 2);
+        assert_eq!(flow.through_cell(&manifold, 2, 1).unwrap(), 1);
+    }
 }
@@ -1,188 +1,965 @@
+mod disjoint_set;
+
+use disjoint_set::DisjointSet;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 use std::fs::{self, File};
 use std::io::{self, Write};
 
 const DEFAULT_INPUT: &str = "../input/day08.txt";
 const DEFAULT_OUTPUT: &str = "output/day08.txt";
 
-#[derive(Debug, Clone, Copy)]
-struct Point {
-    x: i64,
-    y: i64,
-    z: i64,
+/// Points of a common dimension, stored flat: point `i` is
+/// `coords[i * dims..(i + 1) * dims]`.
+#[derive(Debug, Clone, Default)]
+struct Points {
+    dims: usize,
+    coords: Vec<i64>,
+}
+
+impl Points {
+    fn len(&self) -> usize {
+        self.coords.len().checked_div(self.dims).unwrap_or(0)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.coords.is_empty()
+    }
+
+    fn get(&self, i: usize) -> &[i64] {
+        &self.coords[i * self.dims..(i + 1) * self.dims]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Metric {
+    Euclidean,
+    Manhattan,
+    Chebyshev,
 }
 
+impl Metric {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "euclidean" => Some(Metric::Euclidean),
+            "manhattan" => Some(Metric::Manhattan),
+            "chebyshev" => Some(Metric::Chebyshev),
+            _ => None,
+        }
+    }
+
+    /// Combines per-axis gaps into a distance. Euclidean distances are left
+    /// squared, which orders them the same while keeping them exact integers.
+    fn combine<I: Iterator<Item = u64>>(self, gaps: I) -> i128 {
+        match self {
+            Metric::Euclidean => gaps.map(|d| d as i128 * d as i128).sum(),
+            Metric::Manhattan => gaps.map(|d| d as i128).sum(),
+            Metric::Chebyshev => gaps.map(|d| d as i128).max().unwrap_or(0),
+        }
+    }
+
+    fn dist(self, a: &[i64], b: &[i64]) -> i128 {
+        self.combine(a.iter().zip(b).map(|(x, y)| x.abs_diff(*y)))
+    }
+
+    /// The actual distance for a value from [`Metric::dist`], undoing the
+    /// squaring of Euclidean distances.
+    fn actual(self, dist: i128) -> f64 {
+        match self {
+            Metric::Euclidean => (dist as f64).sqrt(),
+            Metric::Manhattan | Metric::Chebyshev => dist as f64,
+        }
+    }
+}
+
+/// Total order on edges: distance under the chosen metric, then the lower
+/// endpoint index, then the higher one. Every edge ordering in this file (the
+/// nearest-pair stream, the MST and its longest edge) follows it, so equal
+/// distances are always resolved by input position rather than by sort or
+/// traversal order.
+type EdgeKey = (i128, usize, usize);
+
 #[derive(Debug, Clone)]
 struct Edge {
     u: usize,
     v: usize,
-    dist_sq: i64,
+    dist: i128,
 }
 
-struct DSU {
-    parent: Vec<usize>,
-    size: Vec<usize>,
-    num_components: usize,
+impl Edge {
+    fn key(&self) -> EdgeKey {
+        (self.dist, self.u, self.v)
+    }
 }
 
-impl DSU {
-    fn new(n: usize) -> Self {
-        DSU {
-            parent: (0..n).collect(),
-            size: vec![1; n],
-            num_components: n,
+fn parse_input(filename: &str) -> Result<Points, String> {
+    let content = fs::read_to_string(filename)
+        .map_err(|_| format!("Error: Could not open file {}", filename))?;
+    parse_points(&content)
+}
+
+/// Reads one point per non-blank line from comma-separated integer
+/// coordinates. The first point fixes the dimension; any line that isn't a
+/// point of that dimension is an error naming its line number.
+fn parse_points(content: &str) -> Result<Points, String> {
+    let mut points = Points::default();
+    for (line_no, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
         }
+        let coords = line
+            .split(',')
+            .map(|s| s.trim().parse::<i64>())
+            .collect::<Result<Vec<i64>, _>>()
+            .map_err(|_| format!("Error: line {} is not a point: '{}'", line_no + 1, line))?;
+        if points.is_empty() {
+            points.dims = coords.len();
+        } else if coords.len() != points.dims {
+            return Err(format!(
+                "Error: line {} has {} coordinates, expected {}",
+                line_no + 1,
+                coords.len(),
+                points.dims
+            ));
+        }
+        points.coords.extend(coords);
+    }
+
+    // Every distance is at most `dims * (2 * max|c|)^2`; refuse inputs where that
+    // wouldn't fit, rather than computing wrong distances.
+    let max_gap = 2 * points
+        .coords
+        .iter()
+        .map(|c| c.unsigned_abs() as i128)
+        .max()
+        .unwrap_or(0);
+    if max_gap
+        .checked_mul(max_gap)
+        .and_then(|sq| sq.checked_mul(points.dims as i128))
+        .is_none()
+    {
+        return Err(format!(
+            "Error: coordinates too large for exact distances in {} dimensions",
+            points.dims
+        ));
     }
+    Ok(points)
+}
+
+/// Points per k-d tree leaf; small enough that scanning a leaf is cheaper than splitting it.
+const LEAF_SIZE: usize = 8;
+
+/// One k-d tree node covering `order[start..end]`.
+struct KdNode {
+    start: usize,
+    end: usize,
+    children: Option<(usize, usize)>,
+}
 
-    fn find(&mut self, i: usize) -> usize {
-        let mut root = i;
-        while root != self.parent[root] {
-            root = self.parent[root];
+/// Static k-d tree over a point set, answering nearest-neighbour queries by
+/// index under a given metric.
+struct KdTree<'a> {
+    points: &'a Points,
+    metric: Metric,
+    order: Vec<usize>,
+    /// Point coordinates permuted into `order`, so leaf scans read contiguous memory.
+    sorted: Vec<i64>,
+    nodes: Vec<KdNode>,
+    /// Each node's bounding box, flat: the low corner then the high corner.
+    boxes: Vec<i64>,
+}
+
+impl<'a> KdTree<'a> {
+    fn new(points: &'a Points, metric: Metric) -> Self {
+        let mut tree = KdTree {
+            points,
+            metric,
+            order: (0..points.len()).collect(),
+            sorted: Vec::with_capacity(points.coords.len()),
+            nodes: Vec::new(),
+            boxes: Vec::new(),
+        };
+        if !points.is_empty() {
+            tree.build(0, points.len());
         }
+        for &i in &tree.order {
+            tree.sorted.extend_from_slice(points.get(i));
+        }
+        tree
+    }
 
-        let mut curr = i;
-        while curr != root {
-            let next = self.parent[curr];
-            self.parent[curr] = root;
-            curr = next;
+    /// Builds the subtree over `order[start..end]` in pre-order and returns its node index.
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let dims = self.points.dims;
+        let mut lo = vec![i64::MAX; dims];
+        let mut hi = vec![i64::MIN; dims];
+        for &i in &self.order[start..end] {
+            for (axis, &c) in self.points.get(i).iter().enumerate() {
+                lo[axis] = lo[axis].min(c);
+                hi[axis] = hi[axis].max(c);
+            }
+        }
+
+        let idx = self.nodes.len();
+        self.nodes.push(KdNode {
+            start,
+            end,
+            children: None,
+        });
+        self.boxes.extend_from_slice(&lo);
+        self.boxes.extend_from_slice(&hi);
+        if end - start > LEAF_SIZE {
+            let axis = (0..dims)
+                .max_by_key(|&a| hi[a].abs_diff(lo[a]))
+                .unwrap_or(0);
+            let mid = start + (end - start) / 2;
+            let points = self.points;
+            self.order[start..end]
+                .select_nth_unstable_by_key(mid - start, |&i| points.get(i)[axis]);
+            let left = self.build(start, mid);
+            let right = self.build(mid, end);
+            self.nodes[idx].children = Some((left, right));
         }
-        root
+        idx
     }
 
-    fn union(&mut self, i: usize, j: usize) -> bool {
-        let root_i = self.find(i);
-        let root_j = self.find(j);
+    /// Smallest possible distance from `p` to any point inside node `idx`'s box.
+    fn box_dist(&self, idx: usize, p: &[i64]) -> i128 {
+        let dims = self.points.dims;
+        let corners = &self.boxes[2 * dims * idx..2 * dims * (idx + 1)];
+        let (lo, hi) = corners.split_at(dims);
+        self.metric
+            .combine(p.iter().zip(lo.iter().zip(hi)).map(|(&c, (&lo, &hi))| {
+                if c < lo {
+                    lo.abs_diff(c)
+                } else if c > hi {
+                    c.abs_diff(hi)
+                } else {
+                    0
+                }
+            }))
+    }
 
-        if root_i != root_j {
-            if self.size[root_i] < self.size[root_j] {
-                self.parent[root_i] = root_j;
-                self.size[root_j] += self.size[root_i];
-            } else {
-                self.parent[root_j] = root_i;
-                self.size[root_i] += self.size[root_j];
+    /// Walks the tree nearest-box-first, offering each point in a surviving leaf to
+    /// `visit`. Subtrees are skipped when `prune(state, node_index, box_dist)` is true.
+    fn search<S, P, V>(&self, query: usize, state: &mut S, prune: P, mut visit: V)
+    where
+        P: Fn(&S, usize, i128) -> bool,
+        V: FnMut(&mut S, usize, i128),
+    {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let dims = self.points.dims;
+        let q = self.points.get(query);
+        let mut stack = vec![(0, self.box_dist(0, q))];
+        while let Some((idx, bound)) = stack.pop() {
+            if prune(state, idx, bound) {
+                continue;
+            }
+            let node = &self.nodes[idx];
+            match node.children {
+                Some((left, right)) => {
+                    let dl = self.box_dist(left, q);
+                    let dr = self.box_dist(right, q);
+                    if dl <= dr {
+                        stack.push((right, dr));
+                        stack.push((left, dl));
+                    } else {
+                        stack.push((left, dl));
+                        stack.push((right, dr));
+                    }
+                }
+                None => {
+                    let coords = &self.sorted[node.start * dims..node.end * dims];
+                    for (&j, p) in self.order[node.start..node.end]
+                        .iter()
+                        .zip(coords.chunks_exact(dims))
+                    {
+                        if j != query {
+                            visit(state, j, self.metric.dist(q, p));
+                        }
+                    }
+                }
             }
-            self.num_components -= 1;
-            true
-        } else {
-            false
         }
     }
+
+    /// The `k` nearest other points to `query`, closest first (ties by index).
+    fn nearest(&self, query: usize, k: usize) -> Vec<(i128, usize)> {
+        let mut best: BinaryHeap<(i128, usize)> = BinaryHeap::with_capacity(k + 1);
+        self.search(
+            query,
+            &mut best,
+            |best, _, bound| best.len() >= k && bound > best.peek().unwrap().0,
+            |best, j, d| {
+                if best.len() < k || (d, j) < *best.peek().unwrap() {
+                    best.push((d, j));
+                    if best.len() > k {
+                        best.pop();
+                    }
+                }
+            },
+        );
+        best.into_sorted_vec()
+    }
+}
+
+/// Yields every pair of points in increasing order of distance without
+/// materialising all n(n-1)/2 pairs. Each point keeps a sorted batch of its
+/// nearest neighbours, refilled with a doubled batch size when used up, and a
+/// heap merges the per-point streams.
+struct NearestPairs<'a> {
+    tree: &'a KdTree<'a>,
+    neighbours: Vec<Vec<(i128, usize)>>,
+    next: Vec<usize>,
+    heap: BinaryHeap<Reverse<(i128, usize, usize, usize)>>,
 }
 
-fn parse_input(filename: &str) -> Vec<Point> {
-    let content = match fs::read_to_string(filename) {
-        Ok(c) => c,
-        Err(_) => {
-            eprintln!("Error: Could not open file {}", filename);
-            return Vec::new();
+impl<'a> NearestPairs<'a> {
+    const INITIAL_BATCH: usize = 1;
+
+    fn new(tree: &'a KdTree<'a>) -> Self {
+        let n = tree.points.len();
+        let mut pairs = NearestPairs {
+            tree,
+            neighbours: vec![Vec::new(); n],
+            next: vec![0; n],
+            heap: BinaryHeap::with_capacity(n),
+        };
+        // Querying in tree order keeps consecutive searches in the same part of the tree.
+        for &i in &tree.order {
+            pairs.neighbours[i] = tree.nearest(i, Self::INITIAL_BATCH);
+            pairs.push_next(i);
         }
-    };
+        pairs
+    }
+
+    /// Queues point `i`'s next unused neighbour, fetching a larger batch if needed.
+    fn push_next(&mut self, i: usize) {
+        let batch = self.neighbours[i].len();
+        if self.next[i] == batch && batch > 0 && batch < self.tree.points.len() - 1 {
+            self.neighbours[i] = self.tree.nearest(i, batch * 2);
+        }
+        if let Some(&(d, j)) = self.neighbours[i].get(self.next[i]) {
+            self.heap.push(Reverse((d, i.min(j), i.max(j), i)));
+        }
+    }
+}
+
+impl Iterator for NearestPairs<'_> {
+    type Item = Edge;
 
-    content
-        .lines()
-        .filter(|line| !line.is_empty())
-        .filter_map(|line| {
-            let parts: Vec<&str> = line.split(',').map(|s| s.trim()).collect();
-            if parts.len() == 3 {
-                let x = parts[0].parse::<i64>().ok()?;
-                let y = parts[1].parse::<i64>().ok()?;
-                let z = parts[2].parse::<i64>().ok()?;
-                Some(Point { x, y, z })
-            } else {
-                None
+    fn next(&mut self) -> Option<Edge> {
+        // Every pair shows up in both endpoints' streams; only the lower index's copy is kept.
+        while let Some(Reverse((dist, u, v, owner))) = self.heap.pop() {
+            self.next[owner] += 1;
+            self.push_next(owner);
+            if owner == u {
+                return Some(Edge { u, v, dist });
             }
-        })
-        .collect()
+        }
+        None
+    }
 }
 
-fn generate_edges(points: &[Point]) -> Vec<Edge> {
-    let n = points.len();
-    if n == 0 {
-        return Vec::new();
+/// Minimum spanning tree under the tree's metric by Borůvka's algorithm: each round, every
+/// component is joined to its nearest point outside it, found with k-d tree
+/// queries that skip subtrees lying entirely inside the query's component.
+fn minimum_spanning_tree(tree: &KdTree) -> Vec<Edge> {
+    let n = tree.points.len();
+    let mut dsu = DisjointSet::new(n);
+    let mut mst = Vec::with_capacity(n.saturating_sub(1));
+    let mut comp = vec![0; n];
+    let mut node_comp: Vec<Option<usize>> = vec![None; tree.nodes.len()];
+    // Per point, a lower bound on the key of its nearest edge leaving its
+    // component, flagged when it is that exact edge.
+    let mut lower_bound: Vec<Option<(EdgeKey, bool)>> = vec![None; n];
+
+    while dsu.component_count() > 1 {
+        for (i, c) in comp.iter_mut().enumerate() {
+            *c = dsu.find(i);
+        }
+        // Children follow their parent in pre-order, so a reverse pass sees them first.
+        for idx in (0..tree.nodes.len()).rev() {
+            let node = &tree.nodes[idx];
+            node_comp[idx] = match node.children {
+                Some((l, r)) if node_comp[l] == node_comp[r] => node_comp[l],
+                Some(_) => None,
+                None => {
+                    let first = comp[tree.order[node.start]];
+                    tree.order[node.start..node.end]
+                        .iter()
+                        .all(|&j| comp[j] == first)
+                        .then_some(first)
+                }
+            };
+        }
+
+        let mut best: Vec<Option<EdgeKey>> = vec![None; n];
+        for &i in &tree.order {
+            let own = comp[i];
+            if let Some((key, exact)) = lower_bound[i] {
+                // Components only grow, so last round's nearest outside point is
+                // still the nearest if it is still outside.
+                let other = if key.1 == i { key.2 } else { key.1 };
+                if exact && comp[other] != own {
+                    best[own] = Some(best[own].map_or(key, |b| b.min(key)));
+                    continue;
+                }
+                if best[own].is_some_and(|b| key >= b) {
+                    continue;
+                }
+            }
+
+            let mut state = (best[own], false);
+            tree.search(
+                i,
+                &mut state,
+                |(bound, _), idx, dist| {
+                    node_comp[idx] == Some(own) || bound.is_some_and(|(d, _, _)| dist > d)
+                },
+                |(bound, found), j, d| {
+                    let key = (d, i.min(j), i.max(j));
+                    if comp[j] != own && bound.is_none_or(|b| key < b) {
+                        *bound = Some(key);
+                        *found = true;
+                    }
+                },
+            );
+            let (bound, found) = state;
+            lower_bound[i] = bound.map(|key| (key, found));
+            best[own] = bound;
+        }
+
+        for (dist, u, v) in best.into_iter().flatten() {
+            if dsu.union(u, v) {
+                mst.push(Edge { u, v, dist });
+            }
+        }
     }
+    mst
+}
 
-    let mut edges = Vec::with_capacity(n * (n - 1) / 2);
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Aggregate {
+    Product,
+    Sum,
+    List,
+}
 
-    for i in 0..n {
-        for j in (i + 1)..n {
-            let dx = points[i].x - points[j].x;
-            let dy = points[i].y - points[j].y;
-            let dz = points[i].z - points[j].z;
-            let dist_sq = dx * dx + dy * dy + dz * dz;
-            edges.push(Edge {
-                u: i,
-                v: j,
-                dist_sq,
-            });
+impl Aggregate {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "product" => Some(Aggregate::Product),
+            "sum" => Some(Aggregate::Sum),
+            "list" => Some(Aggregate::List),
+            _ => None,
         }
     }
-    edges
 }
 
-fn part_one(filename: &str) -> i64 {
-    let points = parse_input(filename);
-    if points.is_empty() {
-        return 0;
+/// Circuits formed by making each of `connections`, as sorted member lists,
+/// largest first (ties by smallest member).
+fn circuits_after(points: &Points, connections: &[Edge]) -> Vec<Vec<usize>> {
+    let mut dsu = DisjointSet::new(points.len());
+    for edge in connections {
+        dsu.union(edge.u, edge.v);
     }
 
-    let mut edges = generate_edges(&points);
+    let mut circuits: Vec<Vec<usize>> = dsu.components().collect();
+    circuits.sort_by_key(|c| Reverse(c.len()));
+    circuits
+}
 
-    edges.sort_by_key(|e| e.dist_sq);
+/// Combines the sizes of the `top` largest circuits.
+fn part_one(circuits: &[Vec<usize>], top: usize, aggregate: Aggregate) -> Result<String, String> {
+    if circuits.is_empty() {
+        return Ok("0".to_string());
+    }
 
-    let k = 1000.min(edges.len());
+    let mut sizes = circuits.iter().take(top).map(|c| c.len());
+    match aggregate {
+        Aggregate::Product => sizes
+            .try_fold(1u128, |acc, size| acc.checked_mul(size as u128))
+            .map(|p| p.to_string())
+            .ok_or_else(|| format!("Product of the {} largest circuit sizes overflows", top)),
+        Aggregate::Sum => Ok(sizes.sum::<usize>().to_string()),
+        Aggregate::List => Ok(sizes
+            .map(|size| size.to_string())
+            .collect::<Vec<_>>()
+            .join(",")),
+    }
+}
 
-    let mut dsu = DSU::new(points.len());
-    for edge in edges.iter().take(k) {
-        dsu.union(edge.u, edge.v);
+fn join_coords(coords: &[i64]) -> String {
+    coords
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+fn print_report(points: &Points, circuits: &[Vec<usize>], connections: usize) {
+    println!(
+        "Circuits after {} connections: {}",
+        connections,
+        circuits.len()
+    );
+    for (i, circuit) in circuits.iter().enumerate() {
+        let members: Vec<String> = circuit
+            .iter()
+            .map(|&m| format!("{}:({})", m, join_coords(points.get(m))))
+            .collect();
+        println!("  #{} size {}: {}", i + 1, circuit.len(), members.join(" "));
     }
+}
+
+fn write_report_json(
+    path: &str,
+    points: &Points,
+    circuits: &[Vec<usize>],
+    connections: usize,
+) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(path)?);
+    writeln!(file, "{{")?;
+    writeln!(file, "  \"connections\": {},", connections)?;
+    writeln!(file, "  \"circuits\": [")?;
+    for (i, circuit) in circuits.iter().enumerate() {
+        let members: Vec<String> = circuit
+            .iter()
+            .map(|&m| {
+                format!(
+                    "{{\"index\": {}, \"coords\": [{}]}}",
+                    m,
+                    join_coords(points.get(m))
+                )
+            })
+            .collect();
+        let separator = if i + 1 < circuits.len() { "," } else { "" };
+        writeln!(
+            file,
+            "    {{\"size\": {}, \"members\": [{}]}}{}",
+            circuit.len(),
+            members.join(", "),
+            separator
+        )?;
+    }
+    writeln!(file, "  ]")?;
+    writeln!(file, "}}")?;
+    file.flush()
+}
+
+/// The last connection needed to join everything into one circuit is the
+/// longest edge of the minimum spanning tree; the answer multiplies its
+/// endpoints' first coordinates.
+fn part_two(points: &Points, mst: &[Edge]) -> i128 {
+    mst.iter().max_by_key(|e| e.key()).map_or(0, |e| {
+        points.get(e.u)[0] as i128 * points.get(e.v)[0] as i128
+    })
+}
 
-    let mut sizes: Vec<i64> = (0..points.len())
-        .filter(|&i| dsu.parent[i] == i)
-        .map(|i| dsu.size[i] as i64)
-        .collect();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ExportFormat {
+    Dot,
+    Json,
+    Obj,
+}
 
-    sizes.sort_by(|a, b| b.cmp(a));
+impl ExportFormat {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "dot" => Some(ExportFormat::Dot),
+            "json" => Some(ExportFormat::Json),
+            "obj" => Some(ExportFormat::Obj),
+            _ => None,
+        }
+    }
+}
 
-    sizes.iter().take(3).product()
+/// Writes the junction boxes and each named set of connections in `format`.
+fn write_export(
+    path: &str,
+    format: ExportFormat,
+    points: &Points,
+    metric: Metric,
+    layers: &[(&str, &[Edge])],
+) -> io::Result<()> {
+    let mut file = io::BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Dot => write_dot(&mut file, points, metric, layers)?,
+        ExportFormat::Json => write_geojson(&mut file, points, metric, layers)?,
+        ExportFormat::Obj => write_obj(&mut file, points, layers)?,
+    }
+    file.flush()
+}
+
+/// Graphviz graph with one edge per connection, coloured by set. The first two
+/// coordinates become pinned positions for `neato -n`.
+fn write_dot<W: Write>(
+    out: &mut W,
+    points: &Points,
+    metric: Metric,
+    layers: &[(&str, &[Edge])],
+) -> io::Result<()> {
+    const COLOURS: [&str; 4] = ["blue", "red", "darkgreen", "orange"];
+
+    writeln!(out, "graph circuits {{")?;
+    writeln!(out, "  node [shape=point];")?;
+    for i in 0..points.len() {
+        let p = points.get(i);
+        write!(out, "  {} [label=\"{}\"", i, join_coords(p))?;
+        if p.len() >= 2 {
+            write!(out, ", pos=\"{},{}!\"", p[0], p[1])?;
+        }
+        writeln!(out, "];")?;
+    }
+    for (layer, (name, edges)) in layers.iter().enumerate() {
+        let colour = COLOURS[layer % COLOURS.len()];
+        for e in edges.iter() {
+            writeln!(
+                out,
+                "  {} -- {} [class=\"{}\", color={}, distance=\"{}\"];",
+                e.u,
+                e.v,
+                name,
+                colour,
+                metric.actual(e.dist)
+            )?;
+        }
+    }
+    writeln!(out, "}}")
 }
 
-fn part_two(filename: &str) -> i64 {
-    let points = parse_input(filename);
-    if points.is_empty() {
-        return 0;
+/// GeoJSON-style feature collection: a `Point` per junction box and a
+/// `LineString` per connection, tagged with its set and distance.
+fn write_geojson<W: Write>(
+    out: &mut W,
+    points: &Points,
+    metric: Metric,
+    layers: &[(&str, &[Edge])],
+) -> io::Result<()> {
+    let mut features = Vec::new();
+    for i in 0..points.len() {
+        features.push(format!(
+            "{{\"type\": \"Feature\", \"geometry\": {{\"type\": \"Point\", \"coordinates\": [{}]}}, \"properties\": {{\"index\": {}}}}}",
+            join_coords(points.get(i)),
+            i
+        ));
+    }
+    for (name, edges) in layers {
+        for e in edges.iter() {
+            features.push(format!(
+                "{{\"type\": \"Feature\", \"geometry\": {{\"type\": \"LineString\", \"coordinates\": [[{}], [{}]]}}, \"properties\": {{\"set\": \"{}\", \"u\": {}, \"v\": {}, \"distance\": {}}}}}",
+                join_coords(points.get(e.u)),
+                join_coords(points.get(e.v)),
+                name,
+                e.u,
+                e.v,
+                metric.actual(e.dist)
+            ));
+        }
     }
 
-    let mut edges = generate_edges(&points);
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"type\": \"FeatureCollection\",")?;
+    writeln!(out, "  \"features\": [")?;
+    for (i, feature) in features.iter().enumerate() {
+        let separator = if i + 1 < features.len() { "," } else { "" };
+        writeln!(out, "    {}{}", feature, separator)?;
+    }
+    writeln!(out, "  ]")?;
+    writeln!(out, "}}")
+}
+
+/// Wavefront OBJ line set: one vertex per junction box (first three
+/// coordinates, zero-padded) and an `l` element per connection, grouped by set.
+fn write_obj<W: Write>(out: &mut W, points: &Points, layers: &[(&str, &[Edge])]) -> io::Result<()> {
+    for i in 0..points.len() {
+        let p = points.get(i);
+        let coord = |axis: usize| p.get(axis).copied().unwrap_or(0);
+        writeln!(out, "v {} {} {}", coord(0), coord(1), coord(2))?;
+    }
+    for (name, edges) in layers {
+        writeln!(out, "g {}", name)?;
+        for e in edges.iter() {
+            writeln!(out, "l {} {}", e.u + 1, e.v + 1)?;
+        }
+    }
+    Ok(())
+}
 
-    edges.sort_by_key(|e| e.dist_sq);
+struct Options {
+    input_path: String,
+    connections: usize,
+    top: usize,
+    aggregate: Aggregate,
+    metric: Metric,
+    report: bool,
+    json_path: Option<String>,
+    exports: Vec<(ExportFormat, String)>,
+}
 
-    let mut dsu = DSU::new(points.len());
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        input_path: DEFAULT_INPUT.to_string(),
+        connections: 1000,
+        top: 3,
+        aggregate: Aggregate::Product,
+        metric: Metric::Euclidean,
+        report: false,
+        json_path: None,
+        exports: Vec::new(),
+    };
 
-    for edge in edges {
-        if dsu.union(edge.u, edge.v) && dsu.num_components == 1 {
-            return points[edge.u].x * points[edge.v].x;
+    let mut iter = args.iter().skip(1);
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--connections" => {
+                options.connections = iter
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or("--connections expects a number of pairs")?;
+            }
+            "--top" => {
+                options.top = iter
+                    .next()
+                    .and_then(|n| n.parse().ok())
+                    .ok_or("--top expects a number of circuits")?;
+            }
+            "--aggregate" => {
+                options.aggregate = iter
+                    .next()
+                    .and_then(|a| Aggregate::parse(a))
+                    .ok_or("--aggregate expects one of product, sum, list")?;
+            }
+            "--metric" => {
+                options.metric = iter
+                    .next()
+                    .and_then(|m| Metric::parse(m))
+                    .ok_or("--metric expects one of euclidean, manhattan, chebyshev")?;
+            }
+            "--report" => options.report = true,
+            "--json" => {
+                options.json_path =
+                    Some(iter.next().ok_or("--json expects an output path")?.clone());
+            }
+            "--export" => {
+                let spec = iter.next().map(|s| s.as_str()).unwrap_or("");
+                let export = spec
+                    .split_once('=')
+                    .and_then(|(format, path)| Some((ExportFormat::parse(format)?, path)))
+                    .ok_or_else(|| {
+                        format!(
+                            "Invalid --export spec '{}', expected dot|json|obj=PATH",
+                            spec
+                        )
+                    })?;
+                options.exports.push((export.0, export.1.to_string()));
+            }
+            _ => options.input_path = arg.clone(),
         }
     }
-    0
+    Ok(options)
 }
 
 fn main() -> io::Result<()> {
     let args: Vec<String> = std::env::args().collect();
-    let input_file = if args.len() > 1 {
-        &args[1]
-    } else {
-        DEFAULT_INPUT
+    let options = match parse_args(&args) {
+        Ok(o) => o,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Ok(());
+        }
     };
 
-    println!("Reading from: {}", input_file);
+    println!("Reading from: {}", options.input_path);
 
-    let p1 = part_one(input_file);
-    let p2 = part_two(input_file);
+    let points = match parse_input(&options.input_path) {
+        Ok(points) => points,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Ok(());
+        }
+    };
+    let tree = KdTree::new(&points, options.metric);
+    let connections: Vec<Edge> = NearestPairs::new(&tree).take(options.connections).collect();
+    let circuits = circuits_after(&points, &connections);
+    let p1 = match part_one(&circuits, options.top, options.aggregate) {
+        Ok(p1) => p1,
+        Err(e) => {
+            eprintln!("{}", e);
+            return Ok(());
+        }
+    };
+    let mst = minimum_spanning_tree(&tree);
+    let p2 = part_two(&points, &mst);
 
     println!("Part 1: {}", p1);
     println!("Part 2: {}", p2);
 
+    if options.report {
+        print_report(&points, &circuits, connections.len());
+    }
+    if let Some(path) = &options.json_path {
+        write_report_json(path, &points, &circuits, connections.len())?;
+    }
+    let layers = [("connections", &connections[..]), ("mst", &mst[..])];
+    for (format, path) in &options.exports {
+        write_export(path, *format, &points, options.metric, &layers)?;
+    }
+
     let mut file = File::create(DEFAULT_OUTPUT)?;
     writeln!(file, "{}\n{}", p1, p2)?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+162,817,812
+57,618,57
+906,360,560
+592,479,940
+352,342,300
+466,668,158
+542,29,236
+431,825,988
+739,650,466
+52,470,668
+216,146,977
+819,987,18
+117,168,530
+805,96,715
+346,949,466
+970,615,88
+941,993,340
+862,61,35
+984,92,344
+425,690,689";
+
+    fn answers(points: &Points, connections: usize) -> (String, i128) {
+        let tree = KdTree::new(points, Metric::Euclidean);
+        let edges: Vec<Edge> = NearestPairs::new(&tree).take(connections).collect();
+        let p1 = part_one(&circuits_after(points, &edges), 3, Aggregate::Product).unwrap();
+        (p1, part_two(points, &minimum_spanning_tree(&tree)))
+    }
+
+    fn keys(edges: impl Iterator<Item = Edge>) -> Vec<EdgeKey> {
+        edges.map(|e| e.key()).collect()
+    }
+
+    fn brute_force_keys(points: &Points, metric: Metric) -> Vec<EdgeKey> {
+        let mut keys = Vec::new();
+        for u in 0..points.len() {
+            for v in u + 1..points.len() {
+                keys.push((metric.dist(points.get(u), points.get(v)), u, v));
+            }
+        }
+        keys.sort();
+        keys
+    }
+
+    fn shuffled(lines: &[&str], seed: u64) -> String {
+        let mut lines = lines.to_vec();
+        let mut state = seed | 1;
+        for i in (1..lines.len()).rev() {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            lines.swap(i, (state % (i as u64 + 1)) as usize);
+        }
+        lines.join("\n")
+    }
+
+    #[test]
+    fn sample_answers() {
+        let points = parse_points(SAMPLE).unwrap();
+        assert_eq!(answers(&points, 10), ("40".to_string(), 25272));
+    }
+
+    #[test]
+    fn permuting_the_sample_keeps_both_answers() {
+        let lines: Vec<&str> = SAMPLE.lines().collect();
+        let mut reversed = lines.clone();
+        reversed.reverse();
+        let mut inputs = vec![reversed.join("\n")];
+        inputs.extend((1..=8).map(|seed| shuffled(&lines, seed)));
+        for input in inputs {
+            let points = parse_points(&input).unwrap();
+            assert_eq!(answers(&points, 10), ("40".to_string(), 25272));
+        }
+    }
+
+    #[test]
+    fn equal_distances_resolve_by_index() {
+        // Evenly spaced along a line, so every gap of the same width ties.
+        let points = parse_points("0,0,0\n2,0,0\n1,0,0\n3,0,0").unwrap();
+        let tree = KdTree::new(&points, Metric::Euclidean);
+        assert_eq!(
+            keys(NearestPairs::new(&tree)),
+            vec![
+                (1, 0, 2),
+                (1, 1, 2),
+                (1, 1, 3),
+                (4, 0, 1),
+                (4, 2, 3),
+                (9, 0, 3)
+            ]
+        );
+        assert_eq!(
+            keys(minimum_spanning_tree(&tree).into_iter()),
+            vec![(1, 0, 2), (1, 1, 2), (1, 1, 3)]
+        );
+        // The last MST edge joins points 1 and 3, at x = 2 and x = 3.
+        assert_eq!(part_two(&points, &minimum_spanning_tree(&tree)), 6);
+    }
+
+    #[test]
+    fn nearest_pairs_match_a_sorted_brute_force() {
+        let mut state = 0x9e37_79b9_7f4a_7c15u64;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state % 7) as i64 - 3
+        };
+        // Small coordinates so that many pairs tie and some points coincide.
+        for dims in 1..=4 {
+            let text: Vec<String> = (0..60)
+                .map(|_| {
+                    (0..dims)
+                        .map(|_| next().to_string())
+                        .collect::<Vec<_>>()
+                        .join(",")
+                })
+                .collect();
+            let points = parse_points(&text.join("\n")).unwrap();
+            for metric in [Metric::Euclidean, Metric::Manhattan, Metric::Chebyshev] {
+                let tree = KdTree::new(&points, metric);
+                assert_eq!(
+                    keys(NearestPairs::new(&tree)),
+                    brute_force_keys(&points, metric),
+                    "{} dimensions, {:?}",
+                    dims,
+                    metric
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn malformed_lines_are_reported() {
+        assert_eq!(
+            parse_points("1,2,3\n\n1,2,x").unwrap_err(),
+            "Error: line 3 is not a point: '1,2,x'"
+        );
+        assert_eq!(
+            parse_points("1,2,3\n4,5").unwrap_err(),
+            "Error: line 2 has 2 coordinates, expected 3"
+        );
+    }
+}
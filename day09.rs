@@ -1,6 +1,7 @@
 use std::cmp::{max, min};
 use std::env;
 use std::fs;
+use std::ops::Range;
 use std::path::Path;
 
 #[derive(Debug, Clone, Copy)]
@@ -17,39 +18,43 @@ fn intervals_overlap(a: i64, b: i64, c: i64, d: i64) -> bool {
     max(min(a, b), min(c, d)) < min(max(a, b), max(c, d))
 }
 
-fn on_segment(px: f64, py: f64, a: Point, b: Point) -> bool {
-    let ax = a.x as f64;
-    let ay = a.y as f64;
-    let bx = b.x as f64;
-    let by = b.y as f64;
+/// Whether the point `(px2 / 2, py2 / 2)` lies on the axis-aligned edge `a`-`b`.
+/// Coordinates are passed doubled so rectangle midpoints stay integers.
+fn on_segment_doubled(px2: i128, py2: i128, a: Point, b: Point) -> bool {
+    let (ax2, ay2) = (2 * a.x as i128, 2 * a.y as i128);
+    let (bx2, by2) = (2 * b.x as i128, 2 * b.y as i128);
 
     if a.x == b.x {
-        (px - ax).abs() < 1e-9 && py >= ay.min(by) && py <= ay.max(by)
+        px2 == ax2 && py2 >= ay2.min(by2) && py2 <= ay2.max(by2)
     } else {
-        (py - ay).abs() < 1e-9 && px >= ax.min(bx) && px <= ax.max(bx)
+        py2 == ay2 && px2 >= ax2.min(bx2) && px2 <= ax2.max(bx2)
     }
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let default_input = "../input/day09.txt";
-    let input_file = if args.len() > 1 {
-        &args[1]
-    } else {
-        default_input
-    };
-
-    println!("Reading from: {}", input_file);
+/// Exact point-in-rectilinear-polygon test on doubled coordinates; points on
+/// the boundary count as inside. Casts a ray towards +x and counts the
+/// vertical edges it crosses, using half-open spans in y so a ray through a
+/// vertex is counted once.
+fn contains_doubled(polygon: &[Point], px2: i128, py2: i128) -> bool {
+    let n = polygon.len();
+    let mut inside = false;
+    for k in 0..n {
+        let a = polygon[k];
+        let b = polygon[(k + 1) % n];
 
-    let content = match fs::read_to_string(input_file) {
-        Ok(c) => c,
-        Err(_) => {
-            eprintln!("Error: Could not open file {}", input_file);
-            return;
+        if on_segment_doubled(px2, py2, a, b) {
+            return true;
         }
-    };
+        if a.x == b.x && (2 * a.y as i128 > py2) != (2 * b.y as i128 > py2) && px2 < 2 * a.x as i128
+        {
+            inside = !inside;
+        }
+    }
+    inside
+}
 
-    let points: Vec<Point> = content
+fn parse_points(content: &str) -> Vec<Point> {
+    content
         .lines()
         .filter_map(|line| {
             if line.trim().is_empty() {
@@ -64,94 +69,271 @@ fn main() {
                 None
             }
         })
-        .collect();
+        .collect()
+}
+
+/// Tiles in the rectangle with opposite corners `a` and `b`. Each side is at
+/// most 2^64 tiles, so this only overflows when both sides span the whole of
+/// `i64`; `main` rules that out with [`bounding_area`].
+fn area(a: Point, b: Point) -> u128 {
+    (a.x.abs_diff(b.x) as u128 + 1) * (a.y.abs_diff(b.y) as u128 + 1)
+}
+
+/// Tiles in the bounding box of `points`, which no rectangle between them
+/// can exceed, or `None` if that doesn't fit in a `u128`.
+fn bounding_area(points: &[Point]) -> Option<u128> {
+    let side = |coord: fn(&Point) -> i64| {
+        let lo = points.iter().map(coord).min().unwrap_or(0);
+        let hi = points.iter().map(coord).max().unwrap_or(0);
+        hi.abs_diff(lo) as u128 + 1
+    };
+    side(|p| p.x).checked_mul(side(|p| p.y))
+}
 
+fn part_one(points: &[Point]) -> u128 {
+    let mut best = 0;
+    for (i, &a) in points.iter().enumerate() {
+        for &b in &points[i + 1..] {
+            best = best.max(area(a, b));
+        }
+    }
+    best
+}
+
+/// Whether the rectangle with opposite corners `a` and `b` lies within the
+/// polygon, boundary included: no edge may cut through its interior, and its
+/// midpoint must be inside.
+fn rect_inside_direct(polygon: &[Point], a: Point, b: Point) -> bool {
+    let min_x = min(a.x, b.x);
+    let max_x = max(a.x, b.x);
+    let min_y = min(a.y, b.y);
+    let max_y = max(a.y, b.y);
+
+    let n = polygon.len();
+    for k in 0..n {
+        let e0 = polygon[k];
+        let e1 = polygon[(k + 1) % n];
+
+        let cuts_through = if e0.x == e1.x {
+            is_between(e0.x, min_x, max_x) && intervals_overlap(e0.y, e1.y, min_y, max_y)
+        } else {
+            is_between(e0.y, min_y, max_y) && intervals_overlap(e0.x, e1.x, min_x, max_x)
+        };
+        if cuts_through {
+            return false;
+        }
+    }
+
+    contains_doubled(
+        polygon,
+        a.x as i128 + b.x as i128,
+        a.y as i128 + b.y as i128,
+    )
+}
+
+/// Part two by checking every candidate rectangle against every polygon edge.
+/// O(n³), kept as a reference for `--validate`.
+fn part_two_direct(points: &[Point]) -> u128 {
     let n = points.len();
-    let mut p1: i64 = 0;
-    let mut p2: i64 = 0;
+    let mut p2: u128 = 0;
 
     for i in 0..n {
         for j in (i + 1)..n {
-            let p_i = points[i];
-            let p_j = points[j];
-
-            let width = (p_i.x - p_j.x).abs() + 1;
-            let height = (p_i.y - p_j.y).abs() + 1;
-            let area = width * height;
-
-            if area > p1 {
-                p1 = area;
+            let area = area(points[i], points[j]);
+            if area > p2 && rect_inside_direct(points, points[i], points[j]) {
+                p2 = area;
             }
+        }
+    }
+    p2
+}
 
-            if area <= p2 {
-                continue;
-            }
+/// The polygon on a compressed grid: one cell per gap between consecutive
+/// distinct vertex coordinates, each entirely inside or outside the polygon,
+/// with a 2D prefix sum of the outside cells.
+struct CompressedPolygon<'a> {
+    polygon: &'a [Point],
+    xs: Vec<i64>,
+    ys: Vec<i64>,
+    stride: usize,
+    /// `outside[r * stride + c]` counts outside cells in rows `..r` and columns `..c`.
+    outside: Vec<u32>,
+}
 
-            let min_x = min(p_i.x, p_j.x);
-            let max_x = max(p_i.x, p_j.x);
-            let min_y = min(p_i.y, p_j.y);
-            let max_y = max(p_i.y, p_j.y);
-
-            let mut cuts_through = false;
-            for k in 0..n {
-                let a = points[k];
-                let b = points[(k + 1) % n];
-
-                if a.x == b.x {
-                    if is_between(a.x, min_x, max_x) && intervals_overlap(a.y, b.y, min_y, max_y) {
-                        cuts_through = true;
-                    }
-                } else if is_between(a.y, min_y, max_y) && intervals_overlap(a.x, b.x, min_x, max_x)
-                {
-                    cuts_through = true;
-                }
+impl<'a> CompressedPolygon<'a> {
+    fn new(polygon: &'a [Point]) -> Self {
+        let mut xs: Vec<i64> = polygon.iter().map(|p| p.x).collect();
+        let mut ys: Vec<i64> = polygon.iter().map(|p| p.y).collect();
+        xs.sort_unstable();
+        xs.dedup();
+        ys.sort_unstable();
+        ys.dedup();
 
-                if cuts_through {
-                    break;
-                }
+        let cols = xs.len().saturating_sub(1);
+        let rows = ys.len().saturating_sub(1);
+        let x_index = |x: i64| xs.partition_point(|&v| v < x);
+        let y_index = |y: i64| ys.partition_point(|&v| v < y);
+
+        // A vertical edge spanning rows lo..hi flips inside/outside for every
+        // cell to its right in those rows; record where each span starts and stops.
+        let mut toggles: Vec<Vec<usize>> = vec![Vec::new(); rows + 1];
+        let n = polygon.len();
+        for k in 0..n {
+            let (a, b) = (polygon[k], polygon[(k + 1) % n]);
+            if a.x == b.x && a.y != b.y {
+                let c = x_index(a.x);
+                toggles[y_index(a.y.min(b.y))].push(c);
+                toggles[y_index(a.y.max(b.y))].push(c);
             }
+        }
 
-            if cuts_through {
-                continue;
+        let stride = cols + 1;
+        let mut outside = vec![0u32; (rows + 1) * stride];
+        let mut crossing = vec![false; cols + 1];
+        for r in 0..rows {
+            for &c in &toggles[r] {
+                crossing[c] = !crossing[c];
             }
+            let mut inside = false;
+            let mut row_sum = 0;
+            for c in 0..cols {
+                inside ^= crossing[c];
+                row_sum += u32::from(!inside);
+                outside[(r + 1) * stride + c + 1] = outside[r * stride + c + 1] + row_sum;
+            }
+        }
 
-            let cx = (p_i.x + p_j.x) as f64 / 2.0;
-            let cy = (p_i.y + p_j.y) as f64 / 2.0;
+        CompressedPolygon {
+            polygon,
+            xs,
+            ys,
+            stride,
+            outside,
+        }
+    }
 
-            let mut on_boundary = false;
-            let mut intersections = 0;
+    fn outside_in(&self, rows: Range<usize>, cols: Range<usize>) -> u32 {
+        let at = |r: usize, c: usize| self.outside[r * self.stride + c];
+        at(rows.end, cols.end) + at(rows.start, cols.start)
+            - at(rows.start, cols.end)
+            - at(rows.end, cols.start)
+    }
 
-            for k in 0..n {
-                let a = points[k];
-                let b = points[(k + 1) % n];
+    fn cell_inside(&self, r: usize, c: usize) -> bool {
+        r + 1 < self.ys.len() && c + 1 < self.xs.len() && self.outside_in(r..r + 1, c..c + 1) == 0
+    }
 
-                if on_segment(cx, cy, a, b) {
-                    on_boundary = true;
-                    break;
-                }
+    /// Grid-line indices `(column, row)` of a vertex.
+    fn index_of(&self, p: Point) -> (usize, usize) {
+        (
+            self.xs.partition_point(|&x| x < p.x),
+            self.ys.partition_point(|&y| y < p.y),
+        )
+    }
 
-                let ay = a.y as f64;
-                let by = b.y as f64;
-                let ax = a.x as f64;
-                let bx = b.x as f64;
+    /// Whether the rectangle between the grid-line corners `a` and `b` lies
+    /// within the polygon, boundary included.
+    fn contains_rect(&self, a: (usize, usize), b: (usize, usize)) -> bool {
+        let (c0, c1) = (a.0.min(b.0), a.0.max(b.0));
+        let (r0, r1) = (a.1.min(b.1), a.1.max(b.1));
+        if c0 < c1 && r0 < r1 {
+            return self.outside_in(r0..r1, c0..c1) == 0;
+        }
 
-                if (ay > cy) != (by > cy) {
-                    let edge_x = (bx - ax) * (cy - ay) / (by - ay) + ax;
-                    if cx < edge_x {
-                        intersections += 1;
-                    }
-                }
-            }
+        // A degenerate rectangle is a segment along a grid line. Each piece of
+        // it between grid lines is inside if a cell on either side is, and
+        // otherwise only if it lies on the boundary. Single points are vertices.
+        if r0 == r1 {
+            let y2 = 2 * self.ys[r0] as i128;
+            (c0..c1).all(|c| {
+                self.cell_inside(r0, c)
+                    || (r0 > 0 && self.cell_inside(r0 - 1, c))
+                    || contains_doubled(
+                        self.polygon,
+                        self.xs[c] as i128 + self.xs[c + 1] as i128,
+                        y2,
+                    )
+            })
+        } else {
+            let x2 = 2 * self.xs[c0] as i128;
+            (r0..r1).all(|r| {
+                self.cell_inside(r, c0)
+                    || (c0 > 0 && self.cell_inside(r, c0 - 1))
+                    || contains_doubled(
+                        self.polygon,
+                        x2,
+                        self.ys[r] as i128 + self.ys[r + 1] as i128,
+                    )
+            })
+        }
+    }
+}
 
-            if on_boundary || (intersections % 2 == 1) {
-                p2 = area;
+/// Part two over the compressed polygon: O(1) containment per candidate
+/// rectangle, so O(n²) overall.
+fn part_two(points: &[Point]) -> u128 {
+    if points.is_empty() {
+        return 0;
+    }
+    let polygon = CompressedPolygon::new(points);
+    let corners: Vec<(usize, usize)> = points.iter().map(|&p| polygon.index_of(p)).collect();
+    let mut best = 0;
+    for i in 0..points.len() {
+        for j in (i + 1)..points.len() {
+            let area = area(points[i], points[j]);
+            if area > best && polygon.contains_rect(corners[i], corners[j]) {
+                best = area;
             }
         }
     }
+    best
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let validate = args.iter().skip(1).any(|a| a == "--validate");
+    let default_input = "../input/day09.txt";
+    let input_file = args
+        .iter()
+        .skip(1)
+        .find(|a| !a.starts_with("--"))
+        .map_or(default_input, |a| a.as_str());
+
+    println!("Reading from: {}", input_file);
+
+    let content = match fs::read_to_string(input_file) {
+        Ok(c) => c,
+        Err(_) => {
+            eprintln!("Error: Could not open file {}", input_file);
+            return;
+        }
+    };
+
+    let points = parse_points(&content);
+    if bounding_area(&points).is_none() {
+        eprintln!(
+            "Error: rectangle areas would overflow; coordinates span all of i64 on both axes"
+        );
+        return;
+    }
+    let p1 = part_one(&points);
+    let p2 = part_two(&points);
 
     println!("Part 1: {}", p1);
     println!("Part 2: {}", p2);
 
+    if validate {
+        let direct = part_two_direct(&points);
+        if direct == p2 {
+            println!("Direct check: OK");
+        } else {
+            println!(
+                "Direct check: MISMATCH (direct: {}, compressed: {})",
+                direct, p2
+            );
+        }
+    }
+
     let output_path = Path::new("output/day09.txt");
     if let Some(parent) = output_path.parent() {
         let _ = fs::create_dir_all(parent);
@@ -161,3 +343,160 @@ fn main() {
         eprintln!("Error writing output: {}", e);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE: &str = "\
+7,1
+11,1
+11,7
+9,7
+9,5
+2,5
+2,3
+7,3";
+
+    /// A square with a notch cut down from the top between x = 3 and x = 7,
+    /// and a collinear vertex at (5, 0) on the bottom edge.
+    const NOTCHED: &str = "\
+0,0
+5,0
+10,0
+10,10
+7,10
+7,4
+3,4
+3,10
+0,10";
+
+    fn rect(polygon: &[Point], a: (i64, i64), b: (i64, i64)) -> bool {
+        rect_inside_direct(polygon, Point { x: a.0, y: a.1 }, Point { x: b.0, y: b.1 })
+    }
+
+    #[test]
+    fn sample_answers() {
+        let points = parse_points(SAMPLE);
+        assert_eq!(part_one(&points), 50);
+        assert_eq!(part_two_direct(&points), 24);
+    }
+
+    #[test]
+    fn boundary_points_are_inside() {
+        let polygon = parse_points(NOTCHED);
+        for (x2, y2) in [(0, 0), (10, 0), (5, 0), (6, 14), (10, 8), (14, 20)] {
+            assert!(contains_doubled(&polygon, x2, y2), "({}, {}) / 2", x2, y2);
+        }
+    }
+
+    #[test]
+    fn interior_and_exterior_points() {
+        let polygon = parse_points(NOTCHED);
+        for (x2, y2) in [(2, 2), (10, 7), (5, 9), (17, 19), (2, 8)] {
+            assert!(contains_doubled(&polygon, x2, y2), "({}, {}) / 2", x2, y2);
+        }
+        // The notch, its opening, and points whose rays pass through vertices.
+        for (x2, y2) in [(10, 12), (7, 9), (10, 20), (-2, 8), (-2, 0), (22, 10)] {
+            assert!(!contains_doubled(&polygon, x2, y2), "({}, {}) / 2", x2, y2);
+        }
+    }
+
+    #[test]
+    fn rectangles_touching_the_boundary() {
+        let polygon = parse_points(NOTCHED);
+        assert!(!rect(&polygon, (0, 0), (10, 10)));
+        assert!(rect(&polygon, (0, 0), (10, 4)));
+        assert!(rect(&polygon, (0, 10), (3, 0)));
+        assert!(rect(&polygon, (7, 0), (10, 10)));
+        assert!(!rect(&polygon, (0, 0), (10, 5)));
+        assert!(!rect(&polygon, (3, 4), (7, 10)));
+        assert!(!rect(&polygon, (0, 4), (7, 10)));
+    }
+
+    #[test]
+    fn zero_width_rectangles() {
+        let polygon = parse_points(NOTCHED);
+        // Along the boundary.
+        assert!(rect(&polygon, (3, 4), (3, 10)));
+        assert!(rect(&polygon, (3, 4), (7, 4)));
+        assert!(rect(&polygon, (0, 0), (0, 10)));
+        assert!(rect(&polygon, (0, 0), (10, 0)));
+        // Through the interior, up to the notch.
+        assert!(rect(&polygon, (5, 0), (5, 4)));
+        assert!(rect(&polygon, (0, 4), (10, 4)));
+        // Across the notch or its opening.
+        assert!(!rect(&polygon, (5, 0), (5, 10)));
+        assert!(!rect(&polygon, (3, 10), (7, 10)));
+        // A single vertex.
+        assert!(rect(&polygon, (7, 10), (7, 10)));
+    }
+
+    /// Corners must lie on the compressed grid lines.
+    fn compressed_rect(polygon: &CompressedPolygon, a: (i64, i64), b: (i64, i64)) -> bool {
+        polygon.contains_rect(
+            polygon.index_of(Point { x: a.0, y: a.1 }),
+            polygon.index_of(Point { x: b.0, y: b.1 }),
+        )
+    }
+
+    #[test]
+    fn compressed_rectangles_touching_the_boundary() {
+        let points = parse_points(NOTCHED);
+        let polygon = CompressedPolygon::new(&points);
+        assert!(!compressed_rect(&polygon, (0, 0), (10, 10)));
+        assert!(compressed_rect(&polygon, (0, 0), (10, 4)));
+        assert!(compressed_rect(&polygon, (0, 10), (3, 0)));
+        assert!(compressed_rect(&polygon, (7, 0), (10, 10)));
+        assert!(!compressed_rect(&polygon, (0, 0), (10, 5)));
+        assert!(!compressed_rect(&polygon, (3, 4), (7, 10)));
+        assert!(!compressed_rect(&polygon, (0, 4), (7, 10)));
+    }
+
+    #[test]
+    fn compressed_zero_width_rectangles() {
+        let points = parse_points(NOTCHED);
+        let polygon = CompressedPolygon::new(&points);
+        // Along the boundary.
+        assert!(compressed_rect(&polygon, (3, 4), (3, 10)));
+        assert!(compressed_rect(&polygon, (3, 4), (7, 4)));
+        assert!(compressed_rect(&polygon, (0, 0), (0, 10)));
+        assert!(compressed_rect(&polygon, (0, 0), (10, 0)));
+        // Through the interior, up to the notch. (5, 4) and (0, 4) are not
+        // vertices but lie on grid lines.
+        assert!(compressed_rect(&polygon, (5, 0), (5, 4)));
+        assert!(compressed_rect(&polygon, (0, 4), (10, 4)));
+        // Across the notch or its opening.
+        assert!(!compressed_rect(&polygon, (5, 0), (5, 10)));
+        assert!(!compressed_rect(&polygon, (3, 10), (7, 10)));
+        // A single vertex.
+        assert!(compressed_rect(&polygon, (7, 10), (7, 10)));
+    }
+
+    #[test]
+    fn compressed_agrees_with_direct_for_every_vertex_pair() {
+        for text in [SAMPLE, NOTCHED] {
+            let points = parse_points(text);
+            let polygon = CompressedPolygon::new(&points);
+            for &a in &points {
+                for &b in &points {
+                    assert_eq!(
+                        polygon.contains_rect(polygon.index_of(a), polygon.index_of(b)),
+                        rect_inside_direct(&points, a, b),
+                        "{:?} to {:?}",
+                        a,
+                        b
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn compressed_answers() {
+        assert_eq!(part_two(&parse_points(SAMPLE)), 24);
+        let notched = parse_points(NOTCHED);
+        assert_eq!(part_two(&notched), 44);
+        assert_eq!(part_two(&notched), part_two_direct(&notched));
+    }
+}
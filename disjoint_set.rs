@@ -0,0 +1,291 @@
+//! Union–find over dense `usize` elements, with optional per-component payloads
+//! and rollback for offline algorithms.
+#![allow(dead_code)]
+
+/// Data carried by each component, combined when two components are joined.
+pub trait Payload: Clone {
+    fn merge(&mut self, other: &Self);
+}
+
+impl Payload for () {
+    fn merge(&mut self, _: &Self) {}
+}
+
+/// Token returned by [`DisjointSet::snapshot`] to roll back to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Snapshot(usize);
+
+/// Undo record for one successful union.
+#[derive(Debug, Clone)]
+struct Merge<P> {
+    child: usize,
+    root: usize,
+    root_payload: P,
+}
+
+#[derive(Debug, Clone)]
+pub struct DisjointSet<P: Payload = ()> {
+    parent: Vec<usize>,
+    size: Vec<usize>,
+    payload: Vec<P>,
+    components: usize,
+    /// Present once a snapshot has been taken; path compression is off from then on.
+    history: Option<Vec<Merge<P>>>,
+}
+
+impl DisjointSet<()> {
+    pub fn new(n: usize) -> Self {
+        DisjointSet::with_payloads(vec![(); n])
+    }
+}
+
+impl<P: Payload> DisjointSet<P> {
+    /// One singleton component per payload.
+    pub fn with_payloads(payload: Vec<P>) -> Self {
+        let n = payload.len();
+        DisjointSet {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+            payload,
+            components: n,
+            history: None,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.parent.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.parent.is_empty()
+    }
+
+    pub fn component_count(&self) -> usize {
+        self.components
+    }
+
+    /// Representative of `x`'s component, compressing the path unless
+    /// rollback is in use.
+    pub fn find(&mut self, x: usize) -> usize {
+        let root = self.root(x);
+        if self.history.is_none() {
+            let mut curr = x;
+            while curr != root {
+                let next = self.parent[curr];
+                self.parent[curr] = root;
+                curr = next;
+            }
+        }
+        root
+    }
+
+    /// Like [`DisjointSet::find`], but without modifying the structure.
+    pub fn root(&self, x: usize) -> usize {
+        let mut root = x;
+        while root != self.parent[root] {
+            root = self.parent[root];
+        }
+        root
+    }
+
+    /// Joins the components of `x` and `y`. Returns false if they were already joined.
+    pub fn union(&mut self, x: usize, y: usize) -> bool {
+        let (mut root, mut child) = (self.find(x), self.find(y));
+        if root == child {
+            return false;
+        }
+        if self.size[root] < self.size[child] {
+            std::mem::swap(&mut root, &mut child);
+        }
+
+        if let Some(history) = &mut self.history {
+            history.push(Merge {
+                child,
+                root,
+                root_payload: self.payload[root].clone(),
+            });
+        }
+        self.parent[child] = root;
+        self.size[root] += self.size[child];
+        let (root_payload, child_payload) = if root < child {
+            let (lo, hi) = self.payload.split_at_mut(child);
+            (&mut lo[root], &hi[0])
+        } else {
+            let (lo, hi) = self.payload.split_at_mut(root);
+            (&mut hi[0], &lo[child])
+        };
+        root_payload.merge(child_payload);
+        self.components -= 1;
+        true
+    }
+
+    pub fn same(&mut self, x: usize, y: usize) -> bool {
+        self.find(x) == self.find(y)
+    }
+
+    pub fn component_size(&self, x: usize) -> usize {
+        self.size[self.root(x)]
+    }
+
+    /// The merged payload of `x`'s component.
+    pub fn payload(&self, x: usize) -> &P {
+        &self.payload[self.root(x)]
+    }
+
+    /// Members of every component, each sorted, ordered by smallest member.
+    pub fn components(&self) -> impl Iterator<Item = Vec<usize>> {
+        let mut slot = vec![usize::MAX; self.len()];
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        for x in 0..self.len() {
+            let root = self.root(x);
+            if slot[root] == usize::MAX {
+                slot[root] = groups.len();
+                groups.push(Vec::new());
+            }
+            groups[slot[root]].push(x);
+        }
+        groups.into_iter()
+    }
+
+    /// Marks the current state so a later [`DisjointSet::rollback`] can return to it.
+    /// Path compression stays disabled afterwards so every union can be undone.
+    pub fn snapshot(&mut self) -> Snapshot {
+        Snapshot(self.history.get_or_insert_with(Vec::new).len())
+    }
+
+    /// Undoes every union made since `snapshot`, most recent first.
+    pub fn rollback(&mut self, snapshot: Snapshot) {
+        let Some(history) = &mut self.history else {
+            return;
+        };
+        for merge in history.drain(snapshot.0..).rev() {
+            self.parent[merge.child] = merge.child;
+            self.size[merge.root] -= self.size[merge.child];
+            self.payload[merge.root] = merge.root_payload;
+            self.components += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct Sum(u64);
+
+    impl Payload for Sum {
+        fn merge(&mut self, other: &Self) {
+            self.0 += other.0;
+        }
+    }
+
+    fn summed(values: &[u64]) -> DisjointSet<Sum> {
+        DisjointSet::with_payloads(values.iter().map(|&v| Sum(v)).collect())
+    }
+
+    fn sizes<P: Payload>(set: &DisjointSet<P>) -> Vec<usize> {
+        (0..set.len()).map(|x| set.component_size(x)).collect()
+    }
+
+    #[test]
+    fn union_and_find() {
+        let mut set = DisjointSet::new(5);
+        assert_eq!(set.component_count(), 5);
+        assert!(set.union(0, 1));
+        assert!(set.union(3, 4));
+        assert!(!set.union(1, 0));
+        assert!(set.same(0, 1));
+        assert!(!set.same(1, 3));
+        assert_eq!(set.find(0), set.find(1));
+        assert_eq!(set.root(4), set.find(3));
+        assert_eq!(set.component_count(), 3);
+        assert_eq!(sizes(&set), vec![2, 2, 1, 2, 2]);
+
+        assert!(set.union(4, 1));
+        assert_eq!(set.component_count(), 2);
+        assert_eq!(sizes(&set), vec![4, 4, 1, 4, 4]);
+        assert_eq!(
+            set.components().collect::<Vec<_>>(),
+            vec![vec![0, 1, 3, 4], vec![2]]
+        );
+    }
+
+    #[test]
+    fn payloads_merge_on_union() {
+        let mut set = summed(&[1, 2, 4, 8]);
+        set.union(0, 1);
+        set.union(3, 2);
+        assert_eq!(set.payload(1), &Sum(3));
+        assert_eq!(set.payload(2), &Sum(12));
+        set.union(2, 0);
+        for x in 0..4 {
+            assert_eq!(set.payload(x), &Sum(15));
+        }
+    }
+
+    #[test]
+    fn rollback_restores_sizes_payloads_and_count() {
+        let mut set = summed(&[1, 2, 4, 8, 16]);
+        set.union(0, 1);
+        set.union(1, 2);
+        let before = (sizes(&set), set.component_count());
+
+        let snapshot = set.snapshot();
+        set.union(3, 4);
+        set.union(4, 0);
+        assert_eq!(set.component_count(), 1);
+        assert_eq!(set.payload(3), &Sum(31));
+
+        set.rollback(snapshot);
+        assert_eq!((sizes(&set), set.component_count()), before);
+        assert_eq!(set.payload(0), &Sum(7));
+        assert_eq!(set.payload(3), &Sum(8));
+        assert_eq!(set.payload(4), &Sum(16));
+        assert!(set.same(0, 2));
+        assert!(!set.same(2, 3));
+    }
+
+    #[test]
+    fn rollback_undoes_a_union_in_either_direction() {
+        // Whichever argument comes first, the smaller component {3} is the
+        // one attached to {0, 1, 2}, and rollback must detach it again.
+        for smaller_first in [true, false] {
+            let mut set = summed(&[1, 2, 4, 8]);
+            set.union(0, 1);
+            set.union(0, 2);
+            let snapshot = set.snapshot();
+            if smaller_first {
+                set.union(3, 0);
+            } else {
+                set.union(0, 3);
+            }
+            assert_eq!(set.component_size(3), 4);
+
+            set.rollback(snapshot);
+            assert_eq!(sizes(&set), vec![3, 3, 3, 1]);
+            assert_eq!(set.payload(0), &Sum(7));
+            assert_eq!(set.payload(3), &Sum(8));
+            assert_eq!(set.component_count(), 2);
+            assert_eq!(set.find(3), 3);
+        }
+    }
+
+    #[test]
+    fn nested_snapshots_roll_back_in_order() {
+        let mut set = DisjointSet::new(4);
+        let outer = set.snapshot();
+        set.union(0, 1);
+        let inner = set.snapshot();
+        set.union(2, 3);
+        set.union(1, 2);
+
+        set.rollback(inner);
+        assert_eq!(sizes(&set), vec![2, 2, 1, 1]);
+        assert_eq!(set.component_count(), 3);
+
+        set.rollback(outer);
+        assert_eq!(sizes(&set), vec![1; 4]);
+        assert_eq!(set.component_count(), 4);
+    }
+}
@@ -0,0 +1,308 @@
+//! Flat row-major 2D grid shared by the grid-based days.
+#![allow(dead_code)]
+use std::fmt;
+use std::ops::{Index, IndexMut};
+
+pub const ORTHOGONAL: [(isize, isize); 4] = [(-1, 0), (0, -1), (0, 1), (1, 0)];
+pub const ALL_DIRECTIONS: [(isize, isize); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Grid<T> {
+    width: usize,
+    height: usize,
+    cells: Vec<T>,
+}
+
+impl<T> Grid<T> {
+    pub fn new(width: usize, height: usize, fill: T) -> Self
+    where
+        T: Clone,
+    {
+        Grid {
+            width,
+            height,
+            cells: vec![fill; width * height],
+        }
+    }
+
+    /// Builds a grid from rows of possibly different lengths, padding short rows with `pad`.
+    pub fn from_rows(rows: Vec<Vec<T>>, pad: T) -> Self
+    where
+        T: Clone,
+    {
+        let height = rows.len();
+        let width = rows.iter().map(|r| r.len()).max().unwrap_or(0);
+        let mut cells = Vec::with_capacity(width * height);
+        for mut row in rows {
+            row.resize(width, pad.clone());
+            cells.extend(row);
+        }
+        Grid {
+            width,
+            height,
+            cells,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    pub fn get(&self, r: usize, c: usize) -> Option<&T> {
+        if r < self.height && c < self.width {
+            Some(&self.cells[r * self.width + c])
+        } else {
+            None
+        }
+    }
+
+    pub fn get_mut(&mut self, r: usize, c: usize) -> Option<&mut T> {
+        if r < self.height && c < self.width {
+            Some(&mut self.cells[r * self.width + c])
+        } else {
+            None
+        }
+    }
+
+    pub fn row(&self, r: usize) -> &[T] {
+        &self.cells[r * self.width..(r + 1) * self.width]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.height).map(move |r| self.row(r))
+    }
+
+    pub fn column(&self, c: usize) -> impl Iterator<Item = &T> {
+        (0..self.height).map(move |r| &self.cells[r * self.width + c])
+    }
+
+    pub fn columns(&self) -> impl Iterator<Item = impl Iterator<Item = &T>> {
+        (0..self.width).map(move |c| self.column(c))
+    }
+
+    pub fn transposed(&self) -> Grid<T>
+    where
+        T: Clone,
+    {
+        let mut cells = Vec::with_capacity(self.cells.len());
+        for c in 0..self.width {
+            cells.extend(self.column(c).cloned());
+        }
+        Grid {
+            width: self.height,
+            height: self.width,
+            cells,
+        }
+    }
+
+    /// Iterates over every `(row, col)` position in row-major order.
+    pub fn positions(&self) -> impl Iterator<Item = (usize, usize)> {
+        let width = self.width;
+        (0..self.height).flat_map(move |r| (0..width).map(move |c| (r, c)))
+    }
+
+    pub fn find<F>(&self, pred: F) -> Option<(usize, usize)>
+    where
+        F: Fn(&T) -> bool,
+    {
+        self.cells
+            .iter()
+            .position(pred)
+            .map(|i| (i / self.width, i % self.width))
+    }
+
+    /// In-bounds positions reached from `(r, c)` by each of `offsets`.
+    pub fn neighbors_with<'a>(
+        &self,
+        r: usize,
+        c: usize,
+        offsets: &'a [(isize, isize)],
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let (height, width) = (self.height as isize, self.width as isize);
+        offsets.iter().filter_map(move |&(dr, dc)| {
+            let nr = r as isize + dr;
+            let nc = c as isize + dc;
+            if nr >= 0 && nr < height && nc >= 0 && nc < width {
+                Some((nr as usize, nc as usize))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Like [`Grid::neighbors_with`], but wraps around the edges as on a torus.
+    pub fn neighbors_wrapping<'a>(
+        &self,
+        r: usize,
+        c: usize,
+        offsets: &'a [(isize, isize)],
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        let (height, width) = (self.height as isize, self.width as isize);
+        offsets.iter().map(move |&(dr, dc)| {
+            let nr = (r as isize + dr).rem_euclid(height);
+            let nc = (c as isize + dc).rem_euclid(width);
+            (nr as usize, nc as usize)
+        })
+    }
+
+    pub fn neighbors4(&self, r: usize, c: usize) -> impl Iterator<Item = (usize, usize)> {
+        self.neighbors_with(r, c, &ORTHOGONAL)
+    }
+
+    pub fn neighbors8(&self, r: usize, c: usize) -> impl Iterator<Item = (usize, usize)> {
+        self.neighbors_with(r, c, &ALL_DIRECTIONS)
+    }
+}
+
+impl Grid<char> {
+    /// Parses one row per line, padding ragged lines with `pad` to the widest line.
+    pub fn from_lines<'a, I>(lines: I, pad: char) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let rows: Vec<Vec<char>> = lines.into_iter().map(|l| l.chars().collect()).collect();
+        Grid::from_rows(rows, pad)
+    }
+}
+
+impl<T> Index<(usize, usize)> for Grid<T> {
+    type Output = T;
+
+    fn index(&self, (r, c): (usize, usize)) -> &T {
+        assert!(
+            r < self.height && c < self.width,
+            "grid index out of bounds"
+        );
+        &self.cells[r * self.width + c]
+    }
+}
+
+impl<T> IndexMut<(usize, usize)> for Grid<T> {
+    fn index_mut(&mut self, (r, c): (usize, usize)) -> &mut T {
+        assert!(
+            r < self.height && c < self.width,
+            "grid index out of bounds"
+        );
+        &mut self.cells[r * self.width + c]
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Grid<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for row in self.rows() {
+            for cell in row {
+                write!(f, "{}", cell)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(positions: impl Iterator<Item = (usize, usize)>) -> Vec<(usize, usize)> {
+        let mut positions: Vec<_> = positions.collect();
+        positions.sort();
+        positions
+    }
+
+    #[test]
+    fn from_lines_pads_short_rows() {
+        let grid = Grid::from_lines(["ab", "", "cde"], '.');
+        assert_eq!((grid.width(), grid.height()), (3, 3));
+        assert_eq!(grid.to_string(), "ab.\n...\ncde\n");
+        assert!(Grid::from_lines([], '.').is_empty());
+    }
+
+    #[test]
+    fn get_and_index() {
+        let mut grid = Grid::from_lines(["ab", "cd"], '.');
+        assert_eq!(grid.get(1, 0), Some(&'c'));
+        assert_eq!(grid.get(2, 0), None);
+        assert_eq!(grid.get(0, 2), None);
+        *grid.get_mut(0, 1).unwrap() = 'x';
+        grid[(1, 1)] = 'y';
+        assert!(grid.get_mut(0, 2).is_none());
+        assert_eq!(grid.to_string(), "ax\ncy\n");
+    }
+
+    #[test]
+    fn find_returns_the_first_match_in_row_major_order() {
+        let grid = Grid::from_lines(["..S", "S.."], '.');
+        assert_eq!(grid.find(|&ch| ch == 'S'), Some((0, 2)));
+        assert_eq!(grid.find(|&ch| ch == '#'), None);
+    }
+
+    #[test]
+    fn neighbors_at_corners_and_edges() {
+        let grid = Grid::new(4, 3, 0);
+        assert_eq!(sorted(grid.neighbors4(0, 0)), vec![(0, 1), (1, 0)]);
+        assert_eq!(sorted(grid.neighbors8(0, 0)), vec![(0, 1), (1, 0), (1, 1)]);
+        assert_eq!(sorted(grid.neighbors4(2, 3)), vec![(1, 3), (2, 2)]);
+        assert_eq!(
+            sorted(grid.neighbors8(0, 2)),
+            vec![(0, 1), (0, 3), (1, 1), (1, 2), (1, 3)]
+        );
+        assert_eq!(grid.neighbors4(1, 1).count(), 4);
+        assert_eq!(grid.neighbors8(1, 1).count(), 8);
+    }
+
+    #[test]
+    fn neighbors_wrapping_crosses_every_edge() {
+        let grid = Grid::new(4, 3, 0);
+        assert_eq!(
+            sorted(grid.neighbors_wrapping(0, 0, &ALL_DIRECTIONS)),
+            vec![
+                (0, 1),
+                (0, 3),
+                (1, 0),
+                (1, 1),
+                (1, 3),
+                (2, 0),
+                (2, 1),
+                (2, 3)
+            ]
+        );
+        assert_eq!(
+            grid.neighbors_wrapping(2, 3, &[(1, 1), (-4, 9)])
+                .collect::<Vec<_>>(),
+            vec![(0, 0), (1, 0)]
+        );
+    }
+
+    #[test]
+    fn transposed_and_columns_of_a_non_square_grid() {
+        let grid = Grid::from_lines(["abc", "def"], '.');
+        let transposed = grid.transposed();
+        assert_eq!((transposed.width(), transposed.height()), (2, 3));
+        assert_eq!(transposed.to_string(), "ad\nbe\ncf\n");
+        assert_eq!(transposed.transposed(), grid);
+
+        let columns: Vec<String> = grid.columns().map(|col| col.collect()).collect();
+        assert_eq!(columns, vec!["ad", "be", "cf"]);
+        assert_eq!(
+            transposed.rows().collect::<Vec<_>>(),
+            vec![['a', 'd'], ['b', 'e'], ['c', 'f']]
+        );
+    }
+}
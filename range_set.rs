@@ -0,0 +1,240 @@
+//! Sorted set of disjoint inclusive ranges with binary-search lookups.
+#![allow(dead_code)]
+use std::cmp;
+use std::fmt;
+
+/// Inclusive range of IDs. Always satisfies `start <= end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Range {
+    start: i64,
+    end: i64,
+}
+
+impl Range {
+    pub fn new(start: i64, end: i64) -> Result<Self, String> {
+        if start > end {
+            return Err(format!("Reversed range: {}-{}", start, end));
+        }
+        Ok(Range { start, end })
+    }
+
+    pub fn start(&self) -> i64 {
+        self.start
+    }
+
+    pub fn end(&self) -> i64 {
+        self.end
+    }
+
+    pub fn contains(&self, id: i64) -> bool {
+        self.start <= id && id <= self.end
+    }
+
+    /// Number of IDs in the range. Widened to `u128` so `i64::MIN..=i64::MAX` fits.
+    pub fn len(&self) -> u128 {
+        (self.end as i128 - self.start as i128) as u128 + 1
+    }
+
+    /// True when `other` overlaps this range or starts right after it ends.
+    fn touches(&self, other: &Range) -> bool {
+        other.start <= self.end || self.end.checked_add(1) == Some(other.start)
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RangeSet {
+    ranges: Vec<Range>,
+}
+
+impl RangeSet {
+    /// Sorts and merges `ranges` into a set of disjoint ranges. Overlapping and
+    /// adjacent ranges (such as `3-5` and `6-8`) are merged into one.
+    pub fn from_ranges<I>(ranges: I) -> Self
+    where
+        I: IntoIterator<Item = Range>,
+    {
+        let mut ranges: Vec<Range> = ranges.into_iter().collect();
+        ranges.sort_by_key(|r| r.start);
+
+        let mut merged: Vec<Range> = Vec::with_capacity(ranges.len());
+        for r in ranges {
+            match merged.last_mut() {
+                Some(last) if last.touches(&r) => last.end = cmp::max(last.end, r.end),
+                _ => merged.push(r),
+            }
+        }
+        RangeSet { ranges: merged }
+    }
+
+    pub fn ranges(&self) -> &[Range] {
+        &self.ranges
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// The uncovered ranges between consecutive merged ranges.
+    pub fn gaps(&self) -> Vec<Range> {
+        self.ranges
+            .windows(2)
+            .map(|w| Range {
+                start: w[0].end + 1,
+                end: w[1].start - 1,
+            })
+            .collect()
+    }
+
+    /// Index of the merged range containing `id`, if any.
+    pub fn find(&self, id: i64) -> Option<usize> {
+        let idx = self.ranges.partition_point(|r| r.end < id);
+        match self.ranges.get(idx) {
+            Some(r) if r.start <= id => Some(idx),
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, id: i64) -> bool {
+        self.find(id).is_some()
+    }
+
+    /// Total number of IDs covered by the set.
+    pub fn len(&self) -> u128 {
+        self.ranges.iter().map(|r| r.len()).sum()
+    }
+
+    /// Number of IDs in `range` that are covered by the set.
+    pub fn count_in(&self, range: Range) -> u128 {
+        let first = self.ranges.partition_point(|r| r.end < range.start);
+        self.ranges[first..]
+            .iter()
+            .take_while(|r| r.start <= range.end)
+            .map(|r| {
+                Range {
+                    start: cmp::max(r.start, range.start),
+                    end: cmp::min(r.end, range.end),
+                }
+                .len()
+            })
+            .sum()
+    }
+
+    pub fn union(&self, other: &RangeSet) -> RangeSet {
+        RangeSet::from_ranges(self.ranges.iter().chain(&other.ranges).copied())
+    }
+
+    pub fn intersection(&self, other: &RangeSet) -> RangeSet {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a, b) = (self.ranges[i], other.ranges[j]);
+            let start = cmp::max(a.start, b.start);
+            let end = cmp::min(a.end, b.end);
+            if start <= end {
+                ranges.push(Range { start, end });
+            }
+            if a.end < b.end {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        RangeSet { ranges }
+    }
+
+    pub fn difference(&self, other: &RangeSet) -> RangeSet {
+        let mut ranges = Vec::new();
+        let mut j = 0;
+        for &r in &self.ranges {
+            while j < other.ranges.len() && other.ranges[j].end < r.start {
+                j += 1;
+            }
+            let mut next = Some(r.start);
+            let mut k = j;
+            while let Some(start) = next {
+                if k >= other.ranges.len() || other.ranges[k].start > r.end {
+                    break;
+                }
+                let cut = other.ranges[k];
+                if cut.start > start {
+                    ranges.push(Range {
+                        start,
+                        end: cut.start - 1,
+                    });
+                }
+                next = if cut.end >= r.end {
+                    None
+                } else {
+                    Some(cut.end + 1)
+                };
+                k += 1;
+            }
+            if let Some(start) = next {
+                ranges.push(Range { start, end: r.end });
+            }
+        }
+        RangeSet { ranges }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn set(ranges: &[(i64, i64)]) -> RangeSet {
+        RangeSet::from_ranges(ranges.iter().map(|&(s, e)| Range::new(s, e).unwrap()))
+    }
+
+    fn bounds(set: &RangeSet) -> Vec<(i64, i64)> {
+        set.ranges().iter().map(|r| (r.start(), r.end())).collect()
+    }
+
+    #[test]
+    fn reversed_range_is_rejected() {
+        assert!(Range::new(5, 3).is_err());
+        assert!(Range::new(3, 3).is_ok());
+    }
+
+    #[test]
+    fn adjacent_ranges_merge() {
+        assert_eq!(bounds(&set(&[(6, 8), (3, 5)])), vec![(3, 8)]);
+        assert_eq!(bounds(&set(&[(3, 5), (7, 8)])), vec![(3, 5), (7, 8)]);
+        assert_eq!(bounds(&set(&[(3, 5), (4, 10), (11, 11)])), vec![(3, 11)]);
+    }
+
+    #[test]
+    fn adjacency_at_i64_max_does_not_overflow() {
+        assert_eq!(
+            bounds(&set(&[(i64::MAX, i64::MAX), (0, 1)])),
+            vec![(0, 1), (i64::MAX, i64::MAX)]
+        );
+    }
+
+    #[test]
+    fn full_i64_range_counts_every_id() {
+        let full = Range::new(i64::MIN, i64::MAX).unwrap();
+        assert_eq!(full.len(), 1u128 << 64);
+        let all = set(&[(i64::MIN, -1), (0, i64::MAX)]);
+        assert_eq!(bounds(&all), vec![(i64::MIN, i64::MAX)]);
+        assert_eq!(all.len(), 1u128 << 64);
+        assert_eq!(all.count_in(full), 1u128 << 64);
+    }
+
+    #[test]
+    fn lookups_and_set_operations() {
+        let a = set(&[(1, 5), (10, 20)]);
+        let b = set(&[(4, 12)]);
+        assert!(a.contains(5) && !a.contains(6) && a.contains(10));
+        assert_eq!(a.count_in(Range::new(3, 11).unwrap()), 5);
+        assert_eq!(bounds(&a.union(&b)), vec![(1, 20)]);
+        assert_eq!(bounds(&a.intersection(&b)), vec![(4, 5), (10, 12)]);
+        assert_eq!(bounds(&a.difference(&b)), vec![(1, 3), (13, 20)]);
+        assert_eq!(a.gaps(), vec![Range::new(6, 9).unwrap()]);
+    }
+}